@@ -31,7 +31,7 @@ pub fn level3() {
 pub fn main() {
     let cli = Cli::parse();
 
-    JloggerBuilder::new()
+    let _guard = JloggerBuilder::new()
         .max_level(log::LevelFilter::Trace)
         .log_file(cli.log_file.as_deref(), false)
         .log_runtime(true)
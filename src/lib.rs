@@ -1,432 +1,7151 @@
 //! A simple log utility.
 
 use log::{self, LevelFilter, Log, Metadata, Record};
+use std::cell::{Cell, RefCell};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::VecDeque;
 use std::fs::{self, File};
-use std::io::{BufRead, BufReader, Write};
-use std::sync::RwLock;
+use std::hash::{Hash, Hasher};
+use std::io::{self, BufRead, BufReader, Write};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicU8, Ordering};
+use std::sync::mpsc::Sender;
+use std::sync::{Mutex, Once, RwLock, TryLockError};
+use std::time::{Duration, Instant};
 
+#[cfg(all(
+    any(target_os = "linux", target_os = "android"),
+    not(any(
+        target_arch = "mips",
+        target_arch = "mips64",
+        target_arch = "sparc",
+        target_arch = "sparc64"
+    ))
+))]
+const O_NONBLOCK: i32 = 0o4000;
+#[cfg(all(
+    any(target_os = "linux", target_os = "android"),
+    any(target_arch = "mips", target_arch = "mips64")
+))]
+const O_NONBLOCK: i32 = 0x80;
+#[cfg(all(
+    any(target_os = "linux", target_os = "android"),
+    any(target_arch = "sparc", target_arch = "sparc64")
+))]
+const O_NONBLOCK: i32 = 0x4000;
+#[cfg(any(
+    target_os = "macos",
+    target_os = "ios",
+    target_os = "freebsd",
+    target_os = "netbsd",
+    target_os = "openbsd"
+))]
+const O_NONBLOCK: i32 = 0x0004;
+
+#[cfg(unix)]
+fn is_fifo(path: &str) -> bool {
+    use std::os::unix::fs::FileTypeExt;
+
+    fs::metadata(path)
+        .map(|m| m.file_type().is_fifo())
+        .unwrap_or(false)
+}
+
+#[cfg(not(unix))]
+fn is_fifo(_path: &str) -> bool {
+    false
+}
+
+/// Open a FIFO for writing without blocking, so that a slow or missing reader never stalls the
+/// logging thread.
+#[cfg(any(
+    target_os = "linux",
+    target_os = "android",
+    target_os = "macos",
+    target_os = "ios",
+    target_os = "freebsd",
+    target_os = "netbsd",
+    target_os = "openbsd"
+))]
+fn open_fifo(path: &str) -> io::Result<File> {
+    use std::os::unix::fs::OpenOptionsExt;
+
+    fs::OpenOptions::new()
+        .write(true)
+        .custom_flags(O_NONBLOCK)
+        .open(path)
+}
+
+#[cfg(not(any(
+    target_os = "linux",
+    target_os = "android",
+    target_os = "macos",
+    target_os = "ios",
+    target_os = "freebsd",
+    target_os = "netbsd",
+    target_os = "openbsd"
+)))]
+fn open_fifo(path: &str) -> io::Result<File> {
+    fs::OpenOptions::new().write(true).open(path)
+}
+
+/// Incremented by the reopen signal handler. Each log file is reopened before its next log
+/// message when it differs from the generation the file was opened at.
+#[cfg(unix)]
+static REOPEN_GENERATION: AtomicU64 = AtomicU64::new(0);
+
+/// Set while a Jlogger is being installed or is installed as the global logger, so that a
+/// second [`JloggerBuilder::try_build`] fails before touching the log files.
+static INSTALLED: AtomicBool = AtomicBool::new(false);
+
+#[cfg(unix)]
+extern "C" fn request_reopen(_signum: std::os::raw::c_int) {
+    REOPEN_GENERATION.fetch_add(1, Ordering::SeqCst);
+}
+
+/// Install the handler requesting the log file to be reopened on `signum`.
+#[cfg(unix)]
+fn install_reopen_handler(signum: std::os::raw::c_int) -> io::Result<()> {
+    const SIG_ERR: usize = !0;
+
+    extern "C" {
+        fn signal(
+            signum: std::os::raw::c_int,
+            handler: extern "C" fn(std::os::raw::c_int),
+        ) -> usize;
+    }
+
+    // Only an atomic increment is done in the handler, which is async-signal-safe.
+    if unsafe { signal(signum, request_reopen) } == SIG_ERR {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok(())
+}
+
+#[cfg(all(feature = "android", target_os = "android"))]
+#[link(name = "log")]
+extern "C" {
+    fn __android_log_write(
+        prio: std::os::raw::c_int,
+        tag: *const std::os::raw::c_char,
+        text: *const std::os::raw::c_char,
+    ) -> std::os::raw::c_int;
+}
+
+#[cfg(all(feature = "windows", windows))]
+#[link(name = "advapi32")]
+extern "system" {
+    fn RegisterEventSourceW(server: *const u16, source: *const u16) -> *mut std::ffi::c_void;
+    fn ReportEventW(
+        event_log: *mut std::ffi::c_void,
+        event_type: u16,
+        category: u16,
+        event_id: u32,
+        user_sid: *mut std::ffi::c_void,
+        num_strings: u16,
+        data_size: u32,
+        strings: *const *const u16,
+        raw_data: *mut std::ffi::c_void,
+    ) -> i32;
+}
+
+/// Handle of a registered Windows Event Log source, see [`JloggerBuilder::log_eventlog`].
+#[cfg(all(feature = "windows", windows))]
+struct EventLog(*mut std::ffi::c_void);
+
+// Event log handles can be used from any thread.
+#[cfg(all(feature = "windows", windows))]
+unsafe impl Send for EventLog {}
+#[cfg(all(feature = "windows", windows))]
+unsafe impl Sync for EventLog {}
+
+/// NUL terminated UTF-16 copy of `s` for the Windows API.
+#[cfg(all(feature = "windows", windows))]
+fn to_wide(s: &str) -> Vec<u16> {
+    s.encode_utf16()
+        .filter(|c| *c != 0)
+        .chain(std::iter::once(0))
+        .collect()
+}
+
+/// Open the log file and take an exclusive advisory lock on it, see
+/// [`JloggerBuilder::exclusive_lock`]. Fails with [`io::ErrorKind::WouldBlock`] if the lock is
+/// held by someone else.
+#[cfg(unix)]
+fn open_locked_log_file(path: &str, append: bool) -> io::Result<File> {
+    use std::os::unix::io::AsRawFd;
+
+    const LOCK_EX: std::os::raw::c_int = 2;
+    const LOCK_NB: std::os::raw::c_int = 4;
+
+    extern "C" {
+        fn flock(fd: std::os::raw::c_int, operation: std::os::raw::c_int) -> std::os::raw::c_int;
+    }
+
+    // Not removed like in open_log_file(), the file may be in use by the lock holder.
+    let file = fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)?;
+    if unsafe { flock(file.as_raw_fd(), LOCK_EX | LOCK_NB) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    if !append {
+        file.set_len(0)?;
+    }
+
+    Ok(file)
+}
+
+/// System boot time in seconds since the Unix epoch, read from `/proc/stat`.
+fn read_boot_time() -> Option<i64> {
+    let f = File::open("/proc/stat").ok()?;
+
+    BufReader::new(f)
+        .lines()
+        .map_while(Result::ok)
+        .find(|l| l.starts_with("btime"))
+        .and_then(|l| l.split_whitespace().nth(1)?.parse().ok())
+}
+
+/// Time elapsed since the start of this process, from `/proc/uptime` and `/proc/self/stat`.
+#[cfg(target_os = "linux")]
+fn read_process_age() -> Option<Duration> {
+    // _SC_CLK_TCK
+    const SC_CLK_TCK: std::os::raw::c_int = 2;
+
+    extern "C" {
+        fn sysconf(name: std::os::raw::c_int) -> std::os::raw::c_long;
+    }
+
+    let uptime: f64 = fs::read_to_string("/proc/uptime")
+        .ok()?
+        .split_whitespace()
+        .next()?
+        .parse()
+        .ok()?;
+    // The process name may contain spaces, the fields after it start with the state (3rd field)
+    // and the start time in clock ticks after boot is the 22nd field.
+    let stat = fs::read_to_string("/proc/self/stat").ok()?;
+    let started: f64 = stat
+        .rsplit_once(')')?
+        .1
+        .split_whitespace()
+        .nth(19)?
+        .parse()
+        .ok()?;
+    let ticks = unsafe { sysconf(SC_CLK_TCK) };
+    if ticks <= 0 {
+        return None;
+    }
+
+    Duration::try_from_secs_f64(uptime - started / ticks as f64).ok()
+}
+
+#[cfg(not(target_os = "linux"))]
+fn read_process_age() -> Option<Duration> {
+    None
+}
+
+fn open_log_file(path: &str, append: bool) -> io::Result<File> {
+    if is_fifo(path) {
+        return open_fifo(path);
+    }
+
+    if !append {
+        let _ = fs::remove_file(path);
+    }
+
+    fs::OpenOptions::new().create(true).append(true).open(path)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum LogTimeFormat {
     TimeStamp,
     TimeLocal,
     TimeNone,
+    Relative,
+    EpochNanos,
+    LocalWithUptime,
 }
 
-pub struct Jlogger {
-    log_console: bool,
-    log_file: Option<RwLock<File>>,
-    log_runtime: bool,
-    time_format: LogTimeFormat,
-    system_start: i64,
-    max_level: LevelFilter,
+impl LogTimeFormat {
+    /// Inverse of `format as u8`, for storing a format in an atomic.
+    fn from_u8(format: u8) -> Self {
+        match format {
+            0 => LogTimeFormat::TimeStamp,
+            1 => LogTimeFormat::TimeLocal,
+            2 => LogTimeFormat::TimeNone,
+            3 => LogTimeFormat::Relative,
+            4 => LogTimeFormat::EpochNanos,
+            _ => LogTimeFormat::LocalWithUptime,
+        }
+    }
 }
 
-impl Jlogger {
-    fn runtime() -> String {
-        std::thread::current()
-            .name()
-            .map(|s| s.to_string())
-            .unwrap_or_else(|| {
-                let exe_cmd = std::env::current_exe().unwrap();
-                exe_cmd
-                    .as_path()
-                    .file_name()
-                    .unwrap()
-                    .to_str()
-                    .unwrap()
-                    .to_string()
-            })
+/// What the seconds of [`LogTimeFormat::TimeStamp`] are counted from.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TimeStampBase {
+    /// Seconds since the system boot, like the kernel log.
+    Boot,
+    /// Seconds since the Unix epoch.
+    Epoch,
+}
+
+/// How the level of a log message is displayed.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LevelStyle {
+    /// Full level name, e.g. `INFO`.
+    Full,
+    /// Single letter, e.g. `I`.
+    Short,
+    /// Single glyph, e.g. `ℹ`.
+    Symbol,
+}
+
+impl LevelStyle {
+    fn token(&self, level: log::Level) -> String {
+        match self {
+            LevelStyle::Full => format!("{:5}", level),
+            LevelStyle::Short => level.as_str()[..1].to_string(),
+            LevelStyle::Symbol => match level {
+                log::Level::Error => "✖",
+                log::Level::Warn => "⚠",
+                log::Level::Info => "ℹ",
+                log::Level::Debug => "•",
+                log::Level::Trace => "·",
+            }
+            .to_string(),
+        }
     }
 }
 
-impl Log for Jlogger {
-    fn enabled(&self, metadata: &Metadata) -> bool {
-        let level = if let Ok(l) = std::env::var("JLOGGER_LEVEL") {
-            match l.as_str() {
-                "off" => LevelFilter::Off,
-                "error" => LevelFilter::Error,
-                "warn" => LevelFilter::Warn,
-                "info" => LevelFilter::Info,
-                "debug" => LevelFilter::Debug,
-                "trace" => LevelFilter::Trace,
-                _ => LevelFilter::Off,
+/// Fields composing a log message, see [`JloggerBuilder::field_order`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LogField {
+    /// Sequence number of the log message, only outputted when [`JloggerBuilder::log_seq`] is
+    /// enabled.
+    Seq,
+    /// Time stamp in the format set by [`JloggerBuilder::log_time`].
+    Timestamp,
+    /// Log level.
+    Level,
+    /// Runtime information, only outputted when [`JloggerBuilder::log_runtime`] is enabled.
+    Runtime,
+    /// Target of the log message, which is the module path by default.
+    Target,
+    /// Version of the crate the log message comes from, only outputted for targets set by
+    /// [`JloggerBuilder::target_version`].
+    Version,
+    /// Severity class for security tooling, only outputted when enabled by
+    /// [`JloggerBuilder::severity_map`].
+    Severity,
+    /// The log message itself.
+    Message,
+}
+
+/// A log message as structured data, see [`JloggerBuilder::record_channel`].
+#[derive(Debug, Clone)]
+pub struct LogRecord {
+    pub level: log::Level,
+    pub timestamp: chrono::DateTime<chrono::Local>,
+    pub target: String,
+    pub file: Option<String>,
+    pub line: Option<u32>,
+    /// Log message without the location prefix added by the jlogger macros.
+    pub message: String,
+    /// Key-values attached to the log message.
+    pub fields: Vec<(String, String)>,
+}
+
+impl LogRecord {
+    fn new(record: &Record, timestamp: chrono::DateTime<chrono::Local>) -> Self {
+        struct Fields(Vec<(String, String)>);
+
+        impl<'kvs> log::kv::VisitSource<'kvs> for Fields {
+            fn visit_pair(
+                &mut self,
+                key: log::kv::Key<'kvs>,
+                value: log::kv::Value<'kvs>,
+            ) -> Result<(), log::kv::Error> {
+                self.0.push((key.to_string(), value.to_string()));
+                Ok(())
             }
-        } else {
-            self.max_level
-        };
+        }
 
-        metadata.level() <= level
+        let mut fields = Fields(Vec::new());
+        let _ = record.key_values().visit(&mut fields);
+
+        LogRecord {
+            level: record.level(),
+            timestamp,
+            target: record.target().to_string(),
+            file: record.file().map(|f| f.to_string()),
+            line: record.line(),
+            message: Jlogger::message_body(record),
+            fields: fields.0,
+        }
     }
+}
 
-    fn log(&self, record: &Record) {
-        if self.enabled(record.metadata()) {
-            let mut log_message = String::new();
+/// Source of the time outputted in log messages, see [`JloggerBuilder::clock`].
+pub trait Clock: Send + Sync {
+    fn now(&self) -> chrono::DateTime<chrono::Local>;
+}
 
-            let now = chrono::Local::now();
-            match self.time_format {
-                LogTimeFormat::TimeStamp => log_message.push_str({
-                    format!(
-                        "{}.{:<09} ",
-                        now.timestamp() - self.system_start,
-                        now.timestamp_nanos() % 1000000000
-                    )
-                    .as_str()
-                }),
-                LogTimeFormat::TimeLocal => {
-                    log_message.push_str(format!("{} ", now.format("%Y-%m-%d %H:%M:%S")).as_str())
-                }
+/// Clock returning the current local time, used by default.
+pub struct SystemClock;
 
-                LogTimeFormat::TimeNone => {}
-            }
+impl Clock for SystemClock {
+    fn now(&self) -> chrono::DateTime<chrono::Local> {
+        chrono::Local::now()
+    }
+}
 
-            log_message.push_str(format!("{:5} ", record.level()).as_str());
+/// Writer of the log file.
+trait LogWrite: Write + Send + Sync {
+    /// Flush the written data to the device, see [`File::sync_data`].
+    fn sync_data(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
 
-            if self.log_runtime {
-                log_message.push_str(format!("{} ", Jlogger::runtime()).as_str());
-            }
+impl LogWrite for File {
+    fn sync_data(&mut self) -> io::Result<()> {
+        File::sync_data(self)
+    }
+}
 
-            log_message.push_str(format!(": {}", record.args()).as_str());
+type LogWriter = Box<dyn LogWrite>;
 
-            if self.log_console {
-                eprintln!("{}", log_message);
-            }
+struct LogFile {
+    name: String,
+    writer: LogWriter,
+    last_sync: Option<Instant>,
+    /// Written before the first log message, see [`JloggerBuilder::session_separator`].
+    header: Option<String>,
+    /// Name the hour is appended to, see [`JloggerBuilder::rotate_hourly`].
+    hourly_base: Option<String>,
+    /// [`REOPEN_GENERATION`] the file was opened at.
+    #[cfg(unix)]
+    reopen_generation: u64,
+}
 
-            if let Some(f) = &self.log_file {
-                let mut fw = f.write().unwrap();
-                writeln!(fw, "{}", log_message).unwrap();
-            }
+impl LogFile {
+    fn new(name: String, writer: LogWriter) -> Self {
+        LogFile {
+            name,
+            writer,
+            last_sync: None,
+            header: None,
+            hourly_base: None,
+            #[cfg(unix)]
+            reopen_generation: REOPEN_GENERATION.load(Ordering::SeqCst),
         }
     }
+}
 
-    fn flush(&self) {}
+/// Line written before the first log message of a run, see
+/// [`JloggerBuilder::session_separator`].
+fn session_header(now: &chrono::DateTime<chrono::Local>) -> String {
+    format!(
+        "===== new session {} pid={} =====\n",
+        now.format("%Y-%m-%d %H:%M:%S"),
+        std::process::id()
+    )
 }
 
-pub struct JloggerBuilder {
-    max_level: LevelFilter,
-    log_console: bool,
-    log_file: Option<RwLock<File>>,
-    log_runtime: bool,
-    time_format: LogTimeFormat,
+/// Name of the log file written during the hour of `now`, see [`JloggerBuilder::rotate_hourly`].
+fn hourly_log_file(base: &str, now: &chrono::DateTime<chrono::Local>) -> String {
+    format!("{}-{}", base, now.format("%Y%m%d-%H"))
 }
 
-impl Default for JloggerBuilder {
-    fn default() -> Self {
-        JloggerBuilder::new()
+/// Remove the oldest hourly log files of `base` so that at most `max` are left, and those last
+/// modified more than `max_age` ago, see [`JloggerBuilder::max_hourly_files`] and
+/// [`JloggerBuilder::max_hourly_age`]. The newest one is never removed for its age.
+fn prune_hourly_log_files(base: &str, max: Option<usize>, max_age: Option<Duration>) {
+    let base = std::path::Path::new(base);
+    let dir = match base.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => std::path::Path::new("."),
+    };
+    let prefix = match base.file_name().and_then(|n| n.to_str()) {
+        Some(name) => format!("{}-", name),
+        None => return,
+    };
+
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(_) => return,
+    };
+    // The suffix is `YYYYMMDD-HH`, so the names sort from the oldest to the newest.
+    let mut hourly: Vec<String> = entries
+        .filter_map(|e| e.ok()?.file_name().into_string().ok())
+        .filter(|n| {
+            n.strip_prefix(prefix.as_str()).is_some_and(|hour| {
+                hour.len() == 11
+                    && hour
+                        .char_indices()
+                        .all(|(i, c)| if i == 8 { c == '-' } else { c.is_ascii_digit() })
+            })
+        })
+        .collect();
+    hourly.sort();
+
+    let excess = max.map_or(0, |max| hourly.len().saturating_sub(max));
+    for (i, name) in hourly.iter().enumerate() {
+        let path = dir.join(name);
+        let stale = || {
+            let modified = fs::metadata(&path).and_then(|m| m.modified());
+            match (max_age, modified) {
+                (Some(max_age), Ok(modified)) => modified
+                    .elapsed()
+                    .is_ok_and(|age| age > max_age && i + 1 < hourly.len()),
+                _ => false,
+            }
+        };
+
+        if i < excess || stale() {
+            let _ = fs::remove_file(&path);
+        }
     }
 }
 
-impl JloggerBuilder {
-    /// Create a new JloggerBuilder which is used to build a Jlogger.
-    ///
-    /// # Examples
-    /// ```
-    ///     use log::LevelFilter;
-    ///     use jlogger::{JloggerBuilder, LogTimeFormat};
-    ///
-    ///     JloggerBuilder::new()
-    ///        .max_level(LevelFilter::Debug)
-    ///        .log_console(true)
-    ///        .log_time(LogTimeFormat::TimeStamp)
-    ///        .log_file(Some("/tmp/my_log.log"), false)
-    ///        .build();
-    ///
-    /// ```
-    pub fn new() -> Self {
-        JloggerBuilder {
-            max_level: LevelFilter::Info,
-            log_console: true,
-            log_file: None,
-            log_runtime: false,
-            time_format: LogTimeFormat::TimeNone,
+/// Log file opened on the first write, see [`JloggerBuilder::lazy_file`].
+struct LazyLogFile {
+    name: String,
+    append: bool,
+    file: Option<File>,
+}
+
+impl Write for LazyLogFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.file.is_none() {
+            self.file = Some(open_log_file(&self.name, self.append)?);
         }
+
+        self.file.as_mut().unwrap().write(buf)
     }
 
-    /// Set the max level to be outputted.
-    /// Log messages with a level below it will not be outputted.
-    /// At runtime, the log level can be filtered though "JLOGGER_LEVEL" environment variable.
-    pub fn max_level(mut self, max_level: LevelFilter) -> Self {
-        self.max_level = max_level;
-        self
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.as_mut().map(|f| f.flush()).unwrap_or(Ok(()))
     }
+}
 
-    /// If enabled, log message will be printed to the console.
-    /// Default is true.
-    pub fn log_console(mut self, log_console: bool) -> Self {
-        self.log_console = log_console;
-        self
+/// Check that the log file `path` can be created later, without creating it, see
+/// [`JloggerBuilder::lazy_file`].
+fn check_log_file_dir(path: &str) -> io::Result<()> {
+    let dir = match std::path::Path::new(path).parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => std::path::Path::new("."),
+    };
+
+    if !fs::metadata(dir)?.is_dir() {
+        return Err(io::Error::other(format!(
+            "{} is not a directory",
+            dir.display()
+        )));
     }
 
-    /// Log file name.
-    /// If specified, log message will be outputted to it.
-    /// If append is true and the log file exists, new messages well be appended to the end of the
-    /// file. Otherwise, a new log file will be created.
-    pub fn log_file(mut self, log_file: Option<&str>, append: bool) -> Self {
-        if let Some(log_file) = log_file {
-            if !append {
-                let _ = fs::remove_file(log_file);
-            }
+    #[cfg(unix)]
+    {
+        use std::os::unix::ffi::OsStrExt;
 
-            self.log_file = Some(RwLock::new(
-                fs::OpenOptions::new()
-                    .create(true)
-                    .write(true)
-                    .append(true)
-                    .open(log_file)
-                    .unwrap(),
-            ));
+        const W_OK: std::os::raw::c_int = 2;
+
+        extern "C" {
+            fn access(path: *const std::os::raw::c_char, mode: std::os::raw::c_int) -> i32;
         }
 
-        self
+        let dir = std::ffi::CString::new(dir.as_os_str().as_bytes())
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        if unsafe { access(dir.as_ptr(), W_OK) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
     }
 
-    /// Add runtime information to log message.
-    /// If the current thread name is set, it will be used as runtime information, otherwise
-    /// process name is used
-    ///
-    /// >DEBUG thread1 : logging from thread thread1.  
-    /// >DEBUG jlogger-cac0970c6f073082 : logging from a thread whose name is not set.
-    ///
-    ///
-    pub fn log_runtime(mut self, log_runtime: bool) -> Self {
-        self.log_runtime = log_runtime;
-        self
+    Ok(())
+}
+
+impl LogWrite for LazyLogFile {
+    fn sync_data(&mut self) -> io::Result<()> {
+        self.file.as_mut().map(|f| f.sync_data()).unwrap_or(Ok(()))
     }
+}
 
-    /// Time stamp string format, only take effect when time stamp is enable in the log.
-    /// * TimeStamp  
-    /// Timestamp (from system boot) will be outputted in the log message.
-    /// > 9080.163365118 DEBUG test_debug_macro : src/lib.rs-364 : this is debug  
-    /// > 9083.164066687 INFO  test_debug_macro : this is info
-    /// * TimeLocal  
-    /// Date and time are printed in the log message.  
-    /// > 2022-05-17 13:00:03 DEBUG : src/lib.rs-363 : this is debug  
-    /// > 2022-05-17 13:00:06 INFO  : this is info
-    /// * TimeNone
-    /// No timestamp included in the log message.
-    pub fn log_time(mut self, time_format: LogTimeFormat) -> Self {
-        self.time_format = time_format;
-        self
+/// Number of independently locked parts of a [`MemoryRing`].
+const MEMORY_RING_SHARDS: usize = 8;
+
+/// Recent log messages kept in memory, see [`JloggerBuilder::memory_ring`].
+/// The log messages are spread over shards with their own lock, so that logging threads rarely
+/// wait for each other or for a snapshot.
+struct MemoryRing {
+    capacity: usize,
+    shard_capacity: usize,
+    next: AtomicU64,
+    shards: Vec<Mutex<VecDeque<(u64, String)>>>,
+}
+
+impl MemoryRing {
+    fn new(capacity: usize) -> Self {
+        MemoryRing {
+            capacity,
+            // Each shard gets every MEMORY_RING_SHARDS-th log message, one more than its share
+            // keeps the most recent `capacity` ones complete across the shards.
+            shard_capacity: capacity.div_ceil(MEMORY_RING_SHARDS) + 1,
+            next: AtomicU64::new(0),
+            shards: (0..MEMORY_RING_SHARDS)
+                .map(|_| Mutex::new(VecDeque::new()))
+                .collect(),
+        }
     }
 
-    /// Build a Jlogger.
-    pub fn build(mut self) {
-        let now = chrono::Local::now().timestamp();
-        let system_start = {
-            if let Ok(f) = fs::OpenOptions::new()
-                .create(false)
-                .write(false)
-                .read(true)
-                .open("/proc/stat")
-            {
-                let mut br = BufReader::new(f);
-                loop {
-                    let mut buf = String::new();
-                    if let Ok(n) = br.read_line(&mut buf) {
-                        if n == 0 {
-                            break now;
-                        }
+    fn push(&self, log_message: String) {
+        let id = self.next.fetch_add(1, Ordering::Relaxed);
+        let mut shard = self.shards[id as usize % MEMORY_RING_SHARDS]
+            .lock()
+            .unwrap();
 
-                        if buf.starts_with("btime") {
-                            let v: Vec<&str> = buf.split_whitespace().into_iter().collect();
-                            break v[1].parse::<i64>().unwrap();
-                        }
-                    }
-                }
-            } else {
-                now
-            }
-        };
+        if shard.len() >= self.shard_capacity {
+            shard.pop_front();
+        }
+        shard.push_back((id, log_message));
+    }
 
-        let logger = Box::new(Jlogger {
-            log_console: self.log_console,
-            log_file: self.log_file.take(),
-            log_runtime: self.log_runtime,
-            time_format: self.time_format,
-            system_start,
-            max_level: self.max_level,
-        });
+    /// The most recent log messages, oldest first, without gaps: every log message up to the
+    /// last one in the snapshot is included.
+    fn snapshot(&self) -> Vec<String> {
+        // Lock every shard, so that no log message is added or dropped while copying.
+        let shards: Vec<_> = self.shards.iter().map(|s| s.lock().unwrap()).collect();
+        // Below the oldest log message of a full shard, some log messages were already dropped.
+        let start = shards
+            .iter()
+            .filter(|s| s.len() >= self.shard_capacity)
+            .filter_map(|s| s.front().map(|(id, _)| *id))
+            .max()
+            .unwrap_or(0);
+        let mut messages: Vec<(u64, String)> = shards
+            .iter()
+            .flat_map(|s| s.iter())
+            .filter(|(id, _)| *id >= start)
+            .cloned()
+            .collect();
+        drop(shards);
 
-        log::set_max_level(LevelFilter::Trace);
-        log::set_boxed_logger(logger).unwrap();
+        messages.sort_unstable_by_key(|(id, _)| *id);
+        // A log message which got its id but is not added yet leaves a gap, the later ones are
+        // left out.
+        let end = messages
+            .iter()
+            .zip(start..)
+            .take_while(|((id, _), expected)| id == expected)
+            .count();
+        messages.truncate(end);
+        let skip = end.saturating_sub(self.capacity);
+        messages.into_iter().skip(skip).map(|(_, m)| m).collect()
     }
 }
 
-#[macro_export]
-macro_rules! jerror{
-    () => {
-        log::error!(
-            "{}-{} : arrived.",
-            file!(),
-            line!(),
-        );
-    };
-    ($val:tt) => {
-        log::error!(
-            "{}-{} : {}",
-            file!(),
-            line!(),
-            $val
-        );
-    };
-    ($fmt:expr,$($val:expr),*) => {{
-        log::error!(
-            "{}-{} : {}",
-            file!(),
-            line!(),
-            format!($fmt, $($val),*)
-        );
-    }};
+/// Unix domain socket log messages are sent to, see [`JloggerBuilder::log_unix_socket`].
+#[cfg(unix)]
+struct UnixSocketSink {
+    path: String,
+    stream: Option<std::os::unix::net::UnixStream>,
 }
 
-#[macro_export]
-macro_rules! jwarn{
-    () => {
-        log::warn!(
-            "{}-{} : arrived.",
-            file!(),
-            line!(),
-        );
-    };
-    ($val:tt) => {
+#[cfg(unix)]
+impl UnixSocketSink {
+    /// Send `log_message`, reconnecting if the socket is not connected or the connection is
+    /// broken.
+    fn send(&mut self, log_message: &str) -> io::Result<()> {
+        if let Some(stream) = self.stream.as_mut() {
+            if stream.write_all(log_message.as_bytes()).is_ok() {
+                return Ok(());
+            }
+            self.stream = None;
+        }
+
+        let mut stream = std::os::unix::net::UnixStream::connect(&self.path)?;
+        stream.write_all(log_message.as_bytes())?;
+        self.stream = Some(stream);
+        Ok(())
+    }
+}
+
+/// What to do with a log message when the device of the log file is full.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DiskFullPolicy {
+    /// Discard the log message and count it as dropped.
+    Drop,
+    /// Output the log message to the console instead.
+    Console,
+    /// Move the log file to `<log file>.1`, replacing the previously moved one to free space,
+    /// and write the log message to a new log file.
+    Rotate,
+}
+
+/// How a log message waits for the log file held by another thread.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LockMode {
+    /// Wait until the log file is released.
+    Block,
+    /// Discard the log message and count it as dropped.
+    TrySkip,
+}
+
+/// Predicate deciding whether a log message is outputted, see [`JloggerBuilder::filter`].
+type Filter = Box<dyn Fn(&Metadata) -> bool + Send + Sync>;
+
+/// Curated combinations of builder settings, see [`JloggerBuilder::preset`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Preset {
+    /// For development: DEBUG level, console with the time stamp since boot and the thread name,
+    /// log file with the local time.
+    DevFriendly,
+    /// For deployed programs: INFO level, console and log file with the local time and the
+    /// thread name, and a banner describing the configuration.
+    Production,
+    /// As short as possible: single letter levels and no time or thread name.
+    Compact,
+}
+
+/// Error returned by [`JloggerBuilder::try_build`].
+#[derive(Debug)]
+pub enum JloggerError {
+    /// An environment variable controlling the logger has a value which can not be parsed.
+    InvalidEnv { name: &'static str, value: String },
+    /// A file needed by the logger can not be written.
+    Io { path: String, error: io::Error },
+    /// The log file is locked by another process, see [`JloggerBuilder::exclusive_lock`].
+    LogFileLocked { path: String },
+    /// A log message template has an unknown placeholder, see
+    /// [`JloggerBuilder::console_template`].
+    InvalidTemplate {
+        template: String,
+        placeholder: String,
+    },
+    /// The reopen signal can not be caught, see [`JloggerBuilder::reopen_signal`].
+    InvalidSignal { signum: i32, error: io::Error },
+    /// Another logger is already installed as the global logger.
+    AlreadyInstalled,
+}
+
+impl std::fmt::Display for JloggerError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            JloggerError::InvalidEnv { name, value } => {
+                write!(f, "invalid value of {}: {:?}", name, value)
+            }
+            JloggerError::Io { path, error } => write!(f, "failed to write {}: {}", path, error),
+            JloggerError::LogFileLocked { path } => {
+                write!(f, "{} is locked by another process", path)
+            }
+            JloggerError::InvalidTemplate {
+                template,
+                placeholder,
+            } => write!(
+                f,
+                "unknown placeholder {{{}}} in template {:?}",
+                placeholder, template
+            ),
+            JloggerError::InvalidSignal { signum, error } => {
+                write!(f, "invalid reopen signal {}: {}", signum, error)
+            }
+            JloggerError::AlreadyInstalled => write!(f, "a logger is already installed"),
+        }
+    }
+}
+
+impl std::error::Error for JloggerError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            JloggerError::InvalidEnv { .. }
+            | JloggerError::LogFileLocked { .. }
+            | JloggerError::InvalidTemplate { .. }
+            | JloggerError::AlreadyInstalled => None,
+            JloggerError::Io { error, .. } | JloggerError::InvalidSignal { error, .. } => {
+                Some(error)
+            }
+        }
+    }
+}
+
+/// Remove ANSI escape sequences (e.g. colors) from `s`, so that they don't end up in the log
+/// file. The console still gets them.
+fn strip_ansi(s: &str) -> std::borrow::Cow<'_, str> {
+    if !s.contains('\x1b') {
+        return s.into();
+    }
+
+    let mut stripped = String::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c != '\x1b' {
+            stripped.push(c);
+            continue;
+        }
+
+        // CSI: parameter and intermediate bytes up to a final byte in '@'..='~', other escape
+        // sequences are two characters long.
+        if chars.next() == Some('[') {
+            for c in chars.by_ref() {
+                if ('@'..='~').contains(&c) {
+                    break;
+                }
+            }
+        }
+    }
+
+    stripped.into()
+}
+
+/// Indent of the continuation lines of a wrapped console line.
+const WRAP_INDENT: usize = 4;
+
+/// Width of the terminal stderr is connected to, `None` if it is not a terminal.
+#[cfg(any(
+    target_os = "linux",
+    target_os = "android",
+    target_os = "macos",
+    target_os = "ios",
+    target_os = "freebsd",
+    target_os = "netbsd",
+    target_os = "openbsd"
+))]
+fn terminal_width() -> Option<usize> {
+    use std::io::IsTerminal;
+
+    #[cfg(all(
+        any(target_os = "linux", target_os = "android"),
+        not(any(
+            target_arch = "mips",
+            target_arch = "mips64",
+            target_arch = "powerpc",
+            target_arch = "powerpc64",
+            target_arch = "sparc",
+            target_arch = "sparc64"
+        ))
+    ))]
+    const TIOCGWINSZ: std::os::raw::c_ulong = 0x5413;
+    #[cfg(not(all(
+        any(target_os = "linux", target_os = "android"),
+        not(any(
+            target_arch = "mips",
+            target_arch = "mips64",
+            target_arch = "powerpc",
+            target_arch = "powerpc64",
+            target_arch = "sparc",
+            target_arch = "sparc64"
+        ))
+    )))]
+    const TIOCGWINSZ: std::os::raw::c_ulong = 0x40087468;
+
+    #[repr(C)]
+    #[derive(Default)]
+    struct Winsize {
+        ws_row: u16,
+        ws_col: u16,
+        ws_xpixel: u16,
+        ws_ypixel: u16,
+    }
+
+    extern "C" {
+        fn ioctl(
+            fd: std::os::raw::c_int,
+            request: std::os::raw::c_ulong,
+            ...
+        ) -> std::os::raw::c_int;
+    }
+
+    if !io::stderr().is_terminal() {
+        return None;
+    }
+
+    let mut size = Winsize::default();
+    if unsafe { ioctl(2, TIOCGWINSZ, &mut size as *mut Winsize) } != 0 || size.ws_col == 0 {
+        return None;
+    }
+
+    Some(size.ws_col as usize)
+}
+
+#[cfg(not(any(
+    target_os = "linux",
+    target_os = "android",
+    target_os = "macos",
+    target_os = "ios",
+    target_os = "freebsd",
+    target_os = "netbsd",
+    target_os = "openbsd"
+)))]
+fn terminal_width() -> Option<usize> {
+    None
+}
+
+/// Wrap each line of `text` longer than `width` characters, preferably at a space, continuation
+/// lines are indented by [`WRAP_INDENT`]. ANSI escape sequences (e.g. colors) take no width.
+fn wrap_lines(text: &str, width: usize) -> String {
+    let mut wrapped = String::with_capacity(text.len());
+
+    for line in text.split_inclusive('\n') {
+        let (line, newline) = match line.strip_suffix('\n') {
+            Some(line) => (line, "\n"),
+            None => (line, ""),
+        };
+
+        let mut rest: Vec<char> = line.chars().collect();
+        let mut avail = width;
+        loop {
+            // Index of the first character which does not fit.
+            let mut visible = 0;
+            let mut escape = false;
+            let over = rest.iter().position(|c| {
+                match (escape, c) {
+                    (false, '\x1b') => escape = true,
+                    (false, _) => visible += 1,
+                    // The final byte of a CSI sequence, `[` is its introducer.
+                    (true, '@'..='~') if *c != '[' => escape = false,
+                    (true, _) => {}
+                }
+                visible > avail
+            });
+            let over = match over {
+                Some(over) => over,
+                None => break,
+            };
+
+            let (end, next) = match rest[1..=over].iter().rposition(|c| *c == ' ') {
+                Some(i) => (i + 1, i + 2),
+                None => (over, over),
+            };
+            wrapped.extend(&rest[..end]);
+            wrapped.push('\n');
+            wrapped.push_str(&" ".repeat(WRAP_INDENT));
+            rest.drain(..next);
+            avail = width - WRAP_INDENT;
+        }
+        wrapped.extend(&rest);
+        wrapped.push_str(newline);
+    }
+
+    wrapped
+}
+
+/// Piece of a log message template, see [`JloggerBuilder::console_template`].
+#[derive(Debug, PartialEq)]
+enum TemplatePart {
+    Literal(String),
+    Time,
+    Level,
+    Target,
+    File,
+    Line,
+    Runtime,
+    Seq,
+    Msg,
+}
+
+/// Parse a log message template, returning the unknown placeholder on error.
+/// `{{` and `}}` are a literal `{` and `}`.
+fn parse_template(template: &str) -> Result<Vec<TemplatePart>, String> {
+    let mut parts = Vec::new();
+    let mut literal = String::new();
+    let mut chars = template.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '{' if chars.peek() == Some(&'{') => {
+                chars.next();
+                literal.push('{');
+            }
+            '}' if chars.peek() == Some(&'}') => {
+                chars.next();
+                literal.push('}');
+            }
+            '{' => {
+                let mut name = String::new();
+                loop {
+                    match chars.next() {
+                        Some('}') => break,
+                        Some(c) => name.push(c),
+                        None => return Err(format!("{{{}", name)),
+                    }
+                }
+
+                let part = match name.as_str() {
+                    "time" => TemplatePart::Time,
+                    "level" => TemplatePart::Level,
+                    "target" => TemplatePart::Target,
+                    "file" => TemplatePart::File,
+                    "line" => TemplatePart::Line,
+                    "runtime" => TemplatePart::Runtime,
+                    "seq" => TemplatePart::Seq,
+                    "msg" => TemplatePart::Msg,
+                    _ => return Err(name),
+                };
+                if !literal.is_empty() {
+                    parts.push(TemplatePart::Literal(std::mem::take(&mut literal)));
+                }
+                parts.push(part);
+            }
+            c => literal.push(c),
+        }
+    }
+
+    if !literal.is_empty() {
+        parts.push(TemplatePart::Literal(literal));
+    }
+
+    Ok(parts)
+}
+
+/// Whether `target` is `prefix` or one of its sub modules.
+fn target_matches(target: &str, prefix: &str) -> bool {
+    target
+        .strip_prefix(prefix)
+        .map(|rest| rest.is_empty() || rest.starts_with("::"))
+        .unwrap_or(false)
+}
+
+/// Last `n` components of `path`, or `path` if it has no more than `n` components.
+fn shorten_path(path: &str, n: usize) -> &str {
+    if n == 0 {
+        return path;
+    }
+
+    path.rmatch_indices(['/', '\\'])
+        .nth(n - 1)
+        .map(|(i, _)| &path[i + 1..])
+        .unwrap_or(path)
+}
+
+/// ANSI foreground colors a target can be shown in, see [`JloggerBuilder::color_targets`].
+const TARGET_COLORS: [u8; 12] = [31, 32, 33, 34, 35, 36, 91, 92, 93, 94, 95, 96];
+
+/// ANSI color code of `target`, always the same for the same target.
+fn target_color(target: &str) -> u8 {
+    let mut hasher = DefaultHasher::new();
+    target.hash(&mut hasher);
+    TARGET_COLORS[(hasher.finish() % TARGET_COLORS.len() as u64) as usize]
+}
+
+/// Split `message` into chunks of at most `limit` bytes, on character boundaries.
+fn split_message(message: &str, limit: usize) -> Vec<&str> {
+    let mut chunks = Vec::new();
+    let mut rest = message;
+
+    while !rest.is_empty() {
+        let mut end = limit.min(rest.len());
+        while !rest.is_char_boundary(end) {
+            end -= 1;
+        }
+
+        // Always make progress, even if the limit is smaller than a character.
+        if end == 0 {
+            end = rest.chars().next().unwrap().len_utf8();
+        }
+
+        let (chunk, remain) = rest.split_at(end);
+        chunks.push(chunk);
+        rest = remain;
+    }
+
+    chunks
+}
+
+enum Throttle {
+    Pass,
+    /// The log message is dropped and a notice should be outputted instead.
+    Notify,
+    Drop,
+}
+
+/// Token bucket refilled with `rate` tokens per second, holding at most `rate` tokens.
+struct TokenBucket {
+    rate: f64,
+    tokens: f64,
+    /// Time of the last refill, `None` before the first log message.
+    last: Option<chrono::DateTime<chrono::Local>>,
+    throttled: bool,
+}
+
+impl TokenBucket {
+    fn new(rate: u32) -> Self {
+        TokenBucket {
+            rate: rate as f64,
+            tokens: rate as f64,
+            last: None,
+            throttled: false,
+        }
+    }
+
+    fn acquire(&mut self, now: chrono::DateTime<chrono::Local>) -> Throttle {
+        if let Some(last) = self.last {
+            let elapsed = (now - last).to_std().unwrap_or_default();
+            self.tokens = (self.tokens + elapsed.as_secs_f64() * self.rate).min(self.rate);
+        }
+        self.last = Some(now);
+
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            self.throttled = false;
+            Throttle::Pass
+        } else if !self.throttled {
+            self.throttled = true;
+            Throttle::Notify
+        } else {
+            Throttle::Drop
+        }
+    }
+}
+
+/// Console log messages collected to be written at once, see [`JloggerBuilder::console_batch`].
+struct ConsoleBatch {
+    interval: Duration,
+    pending: String,
+    since: Instant,
+}
+
+impl ConsoleBatch {
+    /// Add `console_message`, returning the collected log messages if they are due to be
+    /// written.
+    fn add(&mut self, console_message: &str, level: log::Level) -> Option<String> {
+        if self.pending.is_empty() {
+            self.since = Instant::now();
+        }
+        self.pending.push_str(console_message);
+
+        if level == log::Level::Error || self.since.elapsed() >= self.interval {
+            Some(std::mem::take(&mut self.pending))
+        } else {
+            None
+        }
+    }
+}
+
+/// Severity classes of log messages, see [`JloggerBuilder::severity_map`].
+struct SeverityMap {
+    targets: Vec<(String, String)>,
+    levels: Vec<(log::Level, String)>,
+}
+
+impl Default for SeverityMap {
+    fn default() -> Self {
+        SeverityMap {
+            targets: vec![
+                ("audit".to_string(), "audit".to_string()),
+                ("security".to_string(), "security".to_string()),
+            ],
+            levels: Vec::new(),
+        }
+    }
+}
+
+impl SeverityMap {
+    /// Class of the most specific target, otherwise of the level, otherwise `operational`.
+    fn class(&self, target: &str, level: log::Level) -> &str {
+        self.targets
+            .iter()
+            .filter(|(t, _)| target_matches(target, t))
+            .max_by_key(|(t, _)| t.len())
+            .map(|(_, c)| c.as_str())
+            .or_else(|| {
+                self.levels
+                    .iter()
+                    .find(|(l, _)| *l == level)
+                    .map(|(_, c)| c.as_str())
+            })
+            .unwrap_or("operational")
+    }
+}
+
+/// Number of recently seen messages remembered by [`JloggerBuilder::dedup_by_hash`].
+const DEDUP_CACHE_SIZE: usize = 64;
+
+struct DedupEntry {
+    hash: u64,
+    since: chrono::DateTime<chrono::Local>,
+    suppressed: usize,
+    /// Message summarized when the window closes, without the location prefix.
+    body: String,
+    level: log::Level,
+    target: String,
+}
+
+/// A small LRU of recently logged message hashes.
+struct DedupCache {
+    window: Duration,
+    entries: VecDeque<DedupEntry>,
+}
+
+impl DedupCache {
+    fn new(window: Duration) -> Self {
+        DedupCache {
+            window,
+            entries: VecDeque::with_capacity(DEDUP_CACHE_SIZE),
+        }
+    }
+
+    /// Remove the entries whose window is closed at `now`, or all of them if `all` is true,
+    /// returning those which have suppressed duplicates to be summarized.
+    fn close(&mut self, now: chrono::DateTime<chrono::Local>, all: bool) -> Vec<DedupEntry> {
+        let window = self.window;
+        let (closed, open) = std::mem::take(&mut self.entries)
+            .into_iter()
+            .partition(|e| all || (now - e.since).to_std().unwrap_or_default() >= window);
+        self.entries = open;
+
+        closed.into_iter().filter(|e| e.suppressed > 0).collect()
+    }
+
+    /// Check a message against the cache.
+    /// Returns true if `body` is the first occurrence of its message in the window and should be
+    /// logged. The least recently logged entry is returned to be summarized if the cache is
+    /// full.
+    fn check(
+        &mut self,
+        body: &str,
+        record: &Record,
+        now: chrono::DateTime<chrono::Local>,
+    ) -> (bool, Option<DedupEntry>) {
+        let mut hasher = DefaultHasher::new();
+        body.hash(&mut hasher);
+        let hash = hasher.finish();
+
+        if let Some(pos) = self.entries.iter().position(|e| e.hash == hash) {
+            let mut entry = self.entries.remove(pos).unwrap();
+            entry.suppressed += 1;
+            self.entries.push_front(entry);
+            return (false, None);
+        }
+
+        let evicted = if self.entries.len() == DEDUP_CACHE_SIZE {
+            self.entries.pop_back().filter(|e| e.suppressed > 0)
+        } else {
+            None
+        };
+
+        self.entries.push_front(DedupEntry {
+            hash,
+            since: now,
+            suppressed: 0,
+            body: body.to_string(),
+            level: record.level(),
+            target: record.target().to_string(),
+        });
+
+        (true, evicted)
+    }
+}
+
+/// A message logged repeatedly, see [`JloggerBuilder::burst_summary`].
+struct Burst {
+    body: String,
+    since: chrono::DateTime<chrono::Local>,
+    suppressed: usize,
+    level: log::Level,
+    target: String,
+    /// Last suppressed occurrence, including the location prefix of the jlogger macros.
+    last: String,
+}
+
+/// Recently logged messages whose duplicates are suppressed until their window closes.
+struct BurstCache {
+    window: Duration,
+    bursts: VecDeque<Burst>,
+}
+
+impl BurstCache {
+    fn new(window: Duration) -> Self {
+        BurstCache {
+            window,
+            bursts: VecDeque::with_capacity(DEDUP_CACHE_SIZE),
+        }
+    }
+
+    /// Remove the bursts whose window is closed at `now`, or all of them if `all` is true,
+    /// returning those which have suppressed duplicates to be summarized.
+    fn close(&mut self, now: chrono::DateTime<chrono::Local>, all: bool) -> Vec<Burst> {
+        let window = self.window;
+        let mut closed = Vec::new();
+
+        self.bursts.retain_mut(|b| {
+            if all || (now - b.since).to_std().unwrap_or_default() >= window {
+                if b.suppressed > 0 {
+                    closed.push(Burst {
+                        body: std::mem::take(&mut b.body),
+                        since: b.since,
+                        suppressed: b.suppressed,
+                        level: b.level,
+                        target: std::mem::take(&mut b.target),
+                        last: std::mem::take(&mut b.last),
+                    });
+                }
+                false
+            } else {
+                true
+            }
+        });
+
+        if self.bursts.len() == DEDUP_CACHE_SIZE {
+            closed.extend(self.bursts.pop_back().filter(|b| b.suppressed > 0));
+        }
+
+        closed
+    }
+
+    /// Whether `record` is the first occurrence of its message in the window and should be
+    /// logged.
+    fn check(&mut self, body: &str, record: &Record, now: chrono::DateTime<chrono::Local>) -> bool {
+        if let Some(burst) = self.bursts.iter_mut().find(|b| b.body == body) {
+            burst.suppressed += 1;
+            burst.level = record.level();
+            burst.last = record.args().to_string();
+            return false;
+        }
+
+        self.bursts.push_front(Burst {
+            body: body.to_string(),
+            since: now,
+            suppressed: 0,
+            level: record.level(),
+            target: record.target().to_string(),
+            last: String::new(),
+        });
+
+        true
+    }
+}
+
+pub struct Jlogger {
+    log_console: bool,
+    /// Writer set by [`JloggerBuilder::console_writer`], stderr if `None`.
+    console: Mutex<Option<Box<dyn Write + Send>>>,
+    console_closed: AtomicBool,
+    log_file: RwLock<Option<LogFile>>,
+    session_separator: bool,
+    rotate_hourly: bool,
+    max_hourly_files: Option<usize>,
+    max_hourly_age: Option<Duration>,
+    #[cfg(unix)]
+    exclusive_lock: bool,
+    fsync_interval: Option<Duration>,
+    disk_full_policy: DiskFullPolicy,
+    file_lock_mode: LockMode,
+    write_retry: (u32, Duration),
+    dropped_records: AtomicU64,
+    bytes_written: AtomicU64,
+    stats_interval: Option<Duration>,
+    heartbeat: Option<Duration>,
+    /// Set when the [`JloggerGuard`] is dropped, stops the background threads.
+    stopped: AtomicBool,
+    /// Milliseconds since `start_time` when a log message was last outputted, see
+    /// [`JloggerBuilder::heartbeat`].
+    last_logged: AtomicU64,
+    /// Log messages held back while the log file is paused, `None` when not paused.
+    file_paused: Mutex<Option<Vec<String>>>,
+    pause_buffer: usize,
+    level_counts: [AtomicU64; 5],
+    log_runtime: bool,
+    log_thread_id: bool,
+    log_seq: bool,
+    seq: AtomicU64,
+    /// [`LogTimeFormat`] as u8, can be changed at runtime.
+    console_time_format: AtomicU8,
+    file_time_format: AtomicU8,
+    level_style: LevelStyle,
+    level_names: Vec<(log::Level, String)>,
+    field_order: Vec<LogField>,
+    field_separator: String,
+    console_template: Option<Vec<TemplatePart>>,
+    file_template: Option<Vec<TemplatePart>>,
+    dedup: Option<Mutex<DedupCache>>,
+    burst: Option<Mutex<BurstCache>>,
+    console_devices: Vec<RwLock<File>>,
+    console_width: Option<usize>,
+    color_targets: bool,
+    console_rate_limit: Option<Mutex<TokenBucket>>,
+    console_batch: Option<Mutex<ConsoleBatch>>,
+    kmsg: Option<RwLock<File>>,
+    memory_ring: Option<MemoryRing>,
+    #[cfg(unix)]
+    unix_socket: Option<Mutex<UnixSocketSink>>,
+    #[cfg(all(feature = "android", target_os = "android"))]
+    logcat_tag: Option<std::ffi::CString>,
+    #[cfg(all(feature = "windows", windows))]
+    eventlog: Option<EventLog>,
+    record_channel: Option<Sender<LogRecord>>,
+    redactions: Vec<String>,
+    normalize_newlines: bool,
+    path_components: Option<usize>,
+    metadata_only: bool,
+    muted_targets: Vec<String>,
+    filters: Vec<Filter>,
+    target_levels: Vec<(String, LevelFilter)>,
+    /// Loggers for the targets routed away, see [`JloggerBuilder::route`].
+    routes: Vec<(String, Jlogger)>,
+    target_versions: Vec<(String, String)>,
+    severity_map: Option<SeverityMap>,
+    max_line_len_split: Option<usize>,
+    split_id: AtomicU64,
+    prefix_multiline: bool,
+    backtrace_on_error: bool,
+    clock: Box<dyn Clock>,
+    timestamp_base: TimeStampBase,
+    /// System boot time in seconds since the Unix epoch, determined when first needed.
+    boot_start: std::sync::OnceLock<i64>,
+    boot_time: fn() -> Option<i64>,
+    /// Time [`LogTimeFormat::Relative`] counts from, determined when first needed.
+    process_start: std::sync::OnceLock<chrono::DateTime<chrono::Local>>,
+    process_age: fn() -> Option<Duration>,
+    start_time: chrono::DateTime<chrono::Local>,
+    log_utc_offset: bool,
+    pid_file: Option<String>,
+    version: Option<String>,
+    /// Working directory when the logger was built, see [`JloggerBuilder::log_cwd`].
+    cwd: Option<String>,
+    max_level: LevelFilter,
+}
+
+impl Jlogger {
+    fn runtime() -> String {
+        std::thread::current()
+            .name()
+            .map(|s| s.to_string())
+            .unwrap_or_else(|| {
+                let exe_cmd = std::env::current_exe().unwrap();
+                exe_cmd
+                    .as_path()
+                    .file_name()
+                    .unwrap()
+                    .to_str()
+                    .unwrap()
+                    .to_string()
+            })
+    }
+
+    /// Sequential id of the current thread, assigned when the thread logs for the first time.
+    fn thread_id() -> u64 {
+        THREAD_ID.with(|id| {
+            if id.get() == 0 {
+                id.set(NEXT_THREAD_ID.fetch_add(1, Ordering::Relaxed));
+            }
+            id.get()
+        })
+    }
+
+    /// Log message without the location prefix added by the jlogger macros, so that the same
+    /// message from different call sites is considered identical.
+    fn message_body(record: &Record) -> String {
+        let message = record.args().to_string();
+
+        if let (Some(file), Some(line)) = (record.file(), record.line()) {
+            let prefix = format!("{}-{} : ", file, line);
+            if let Some(body) = message.strip_prefix(prefix.as_str()) {
+                return body.to_string();
+            }
+        }
+
+        message
+    }
+
+    /// Log message with the file path of the location prefix added by the jlogger macros
+    /// shortened, see [`JloggerBuilder::path_components`].
+    fn message_text(&self, record: &Record) -> String {
+        let message = record.args().to_string();
+
+        if let (Some(n), Some(file), Some(line)) =
+            (self.path_components, record.file(), record.line())
+        {
+            let prefix = format!("{}-{} : ", file, line);
+            if let Some(body) = message.strip_prefix(prefix.as_str()) {
+                return format!("{}-{} : {}", shorten_path(file, n), line, body);
+            }
+        }
+
+        message
+    }
+
+    /// Seconds since the Unix epoch of the system boot. The boot time is only read when it is
+    /// needed for the first time, falling back to that time if it is unknown.
+    fn boot_start(&self, now: &chrono::DateTime<chrono::Local>) -> i64 {
+        *self
+            .boot_start
+            .get_or_init(|| (self.boot_time)().unwrap_or_else(|| now.timestamp()))
+    }
+
+    /// Seconds [`LogTimeFormat::TimeStamp`] counts from.
+    fn timestamp_start(&self, now: &chrono::DateTime<chrono::Local>) -> i64 {
+        match self.timestamp_base {
+            TimeStampBase::Boot => self.boot_start(now),
+            TimeStampBase::Epoch => 0,
+        }
+    }
+
+    /// Start time of the process, or of the logger if it is unknown.
+    fn process_start(&self) -> chrono::DateTime<chrono::Local> {
+        *self.process_start.get_or_init(|| {
+            match (self.process_age)().and_then(|age| chrono::Duration::from_std(age).ok()) {
+                Some(age) => self.clock.now() - age,
+                None => self.start_time,
+            }
+        })
+    }
+
+    fn console_time_format(&self) -> LogTimeFormat {
+        LogTimeFormat::from_u8(self.console_time_format.load(Ordering::Relaxed))
+    }
+
+    fn file_time_format(&self) -> LogTimeFormat {
+        LogTimeFormat::from_u8(self.file_time_format.load(Ordering::Relaxed))
+    }
+
+    fn banner(&self) -> String {
+        format!(
+            "jlogger started: {}level={} console={} file={} console_time={:?} file_time={:?} runtime={}{}",
+            self.version
+                .as_ref()
+                .map(|v| format!("version={} ", v))
+                .unwrap_or_default(),
+            self.max_level,
+            self.log_console,
+            self.log_file
+                .read()
+                .unwrap()
+                .as_ref()
+                .map(|f| f.name.as_str())
+                .unwrap_or("none"),
+            self.console_time_format(),
+            self.file_time_format(),
+            self.log_runtime,
+            self.cwd
+                .as_ref()
+                .map(|d| format!(" cwd={}", d))
+                .unwrap_or_default()
+        )
+    }
+
+    /// Time stamp in `time_format`, `None` for [`LogTimeFormat::TimeNone`].
+    fn time_token(
+        &self,
+        time_format: LogTimeFormat,
+        now: &chrono::DateTime<chrono::Local>,
+    ) -> Option<String> {
+        match time_format {
+            LogTimeFormat::TimeStamp => Some(format!(
+                "{}.{:<09}",
+                now.timestamp() - self.timestamp_start(now),
+                now.timestamp_subsec_nanos()
+            )),
+            LogTimeFormat::TimeLocal => Some(
+                now.format(if self.log_utc_offset {
+                    "%Y-%m-%d %H:%M:%S %:z"
+                } else {
+                    "%Y-%m-%d %H:%M:%S"
+                })
+                .to_string(),
+            ),
+            LogTimeFormat::TimeNone => None,
+            LogTimeFormat::Relative => {
+                let elapsed = (*now - self.process_start()).num_milliseconds().max(0);
+                Some(format!("+{}.{:03}s", elapsed / 1000, elapsed % 1000))
+            }
+            // Nanoseconds overflow i64 after 2262, fall back to milliseconds.
+            LogTimeFormat::EpochNanos => Some(
+                now.timestamp_nanos_opt()
+                    .unwrap_or_else(|| now.timestamp_millis())
+                    .to_string(),
+            ),
+            // The uptime counts from the boot whatever the base of TimeStamp is.
+            LogTimeFormat::LocalWithUptime => Some(format!(
+                "{} [{}.{:<09}]",
+                self.time_token(LogTimeFormat::TimeLocal, now)?,
+                now.timestamp() - self.boot_start(now),
+                now.timestamp_subsec_nanos()
+            )),
+        }
+    }
+
+    /// `target`, colored on the console if [`JloggerBuilder::color_targets`] is enabled.
+    fn target_token<'a>(&self, target: &'a str, console: bool) -> std::borrow::Cow<'a, str> {
+        if console && self.color_targets {
+            format!("\x1b[{}m{}\x1b[0m", target_color(target), target).into()
+        } else {
+            target.into()
+        }
+    }
+
+    fn level_token(&self, level: log::Level) -> String {
+        self.level_names
+            .iter()
+            .find(|(l, _)| *l == level)
+            .map(|(_, name)| name.clone())
+            .unwrap_or_else(|| self.level_style.token(level))
+    }
+
+    /// Log message formatted according to `template`, see [`JloggerBuilder::console_template`].
+    fn render_template(
+        &self,
+        template: &[TemplatePart],
+        record: &Record,
+        time_format: LogTimeFormat,
+        now: &chrono::DateTime<chrono::Local>,
+        seq: u64,
+        console: bool,
+    ) -> String {
+        let mut log_message = String::new();
+
+        for part in template {
+            match part {
+                TemplatePart::Literal(s) => log_message.push_str(s),
+                TemplatePart::Time => {
+                    log_message.push_str(&self.time_token(time_format, now).unwrap_or_default())
+                }
+                TemplatePart::Level => log_message.push_str(&self.level_token(record.level())),
+                TemplatePart::Target => {
+                    log_message.push_str(&self.target_token(record.target(), console))
+                }
+                TemplatePart::File => {
+                    let file = record.file().unwrap_or("");
+                    log_message.push_str(match self.path_components {
+                        Some(n) => shorten_path(file, n),
+                        None => file,
+                    })
+                }
+                TemplatePart::Line => {
+                    if let Some(line) = record.line() {
+                        log_message.push_str(&line.to_string())
+                    }
+                }
+                TemplatePart::Runtime => log_message.push_str(&Jlogger::runtime()),
+                TemplatePart::Seq => log_message.push_str(&seq.to_string()),
+                TemplatePart::Msg => log_message.push_str(&Jlogger::message_body(record)),
+            }
+        }
+
+        log_message
+    }
+
+    /// Log message formatted according to the field order, `console` tells whether it is
+    /// outputted to the console.
+    fn format_message(
+        &self,
+        record: &Record,
+        time_format: LogTimeFormat,
+        now: &chrono::DateTime<chrono::Local>,
+        seq: u64,
+        console: bool,
+    ) -> String {
+        let mut log_message = String::new();
+
+        for field in &self.field_order {
+            match field {
+                LogField::Seq => {
+                    if self.log_seq {
+                        log_message.push_str(format!("#{}{}", seq, self.field_separator).as_str());
+                    }
+                }
+                LogField::Timestamp => {
+                    if let Some(time) = self.time_token(time_format, now) {
+                        log_message.push_str(format!("{}{}", time, self.field_separator).as_str())
+                    }
+                }
+                LogField::Level => log_message.push_str(
+                    format!(
+                        "{}{}",
+                        self.level_token(record.level()),
+                        self.field_separator
+                    )
+                    .as_str(),
+                ),
+                LogField::Runtime => {
+                    if self.log_thread_id {
+                        log_message.push_str(
+                            format!(
+                                "{}#{}{}",
+                                Jlogger::runtime(),
+                                Jlogger::thread_id(),
+                                self.field_separator
+                            )
+                            .as_str(),
+                        );
+                    } else if self.log_runtime {
+                        log_message.push_str(
+                            format!("{}{}", Jlogger::runtime(), self.field_separator).as_str(),
+                        );
+                    }
+                }
+                LogField::Target => log_message.push_str(
+                    format!(
+                        "{}{}",
+                        self.target_token(record.target(), console),
+                        self.field_separator
+                    )
+                    .as_str(),
+                ),
+                LogField::Version => {
+                    if let Some((target, version)) = self
+                        .target_versions
+                        .iter()
+                        .filter(|(t, _)| target_matches(record.target(), t))
+                        .max_by_key(|(t, _)| t.len())
+                    {
+                        log_message.push_str(
+                            format!("{}@{}{}", target, version, self.field_separator).as_str(),
+                        );
+                    }
+                }
+                LogField::Severity => {
+                    if let Some(map) = &self.severity_map {
+                        log_message.push_str(
+                            format!(
+                                "severity={}{}",
+                                map.class(record.target(), record.level()),
+                                self.field_separator
+                            )
+                            .as_str(),
+                        );
+                    }
+                }
+                LogField::Message => {
+                    CORRELATION_ID.with(|id| {
+                        if let Some(id) = id.borrow().as_deref() {
+                            log_message.push_str(format!("[{}] ", id).as_str());
+                        }
+                    });
+                    log_message.push_str(format!(": {}", self.message_text(record)).as_str())
+                }
+            }
+        }
+
+        log_message
+    }
+
+    /// Time of the log message being outputted, see [`RECORD_TIME`].
+    fn now(&self) -> chrono::DateTime<chrono::Local> {
+        RECORD_TIME
+            .with(|t| t.get())
+            .unwrap_or_else(|| self.clock.now())
+    }
+
+    fn write_record(&self, record: &Record) {
+        let now = self.now();
+        let seq = if self.log_seq {
+            self.seq.fetch_add(1, Ordering::Relaxed) + 1
+        } else {
+            0
+        };
+        let console_time_format = self.console_time_format();
+        let file_time_format = self.file_time_format();
+        let mut console_message = match &self.console_template {
+            Some(t) => self.render_template(t, record, console_time_format, &now, seq, true),
+            None => self.format_message(record, console_time_format, &now, seq, true),
+        };
+        console_message.push('\n');
+
+        let throttle = self
+            .console_rate_limit
+            .as_ref()
+            .map(|b| b.lock().unwrap().acquire(self.clock.now()))
+            .unwrap_or(Throttle::Pass);
+        match throttle {
+            Throttle::Pass => match &self.console_batch {
+                Some(batch) => {
+                    // Written under the lock to keep the order of the batches.
+                    let mut batch = batch.lock().unwrap();
+                    if let Some(pending) = batch.add(&console_message, record.level()) {
+                        self.write_console(&pending);
+                    }
+                }
+                None => self.write_console(&console_message),
+            },
+            Throttle::Notify => {
+                let mut notice = self.format_message(
+                    &Record::builder()
+                        .level(log::Level::Warn)
+                        .target(module_path!())
+                        .args(format_args!("(console throttled)"))
+                        .build(),
+                    console_time_format,
+                    &now,
+                    seq,
+                    true,
+                );
+                notice.push('\n');
+                self.write_console(&notice);
+            }
+            Throttle::Drop => {}
+        }
+
+        if let Some(kmsg) = &self.kmsg {
+            // The kernel adds its own time stamp, one write per log message.
+            let kmsg_message = format!(
+                "<{}>{}\n",
+                Jlogger::kmsg_priority(record.level()),
+                self.format_message(record, LogTimeFormat::TimeNone, &now, seq, false)
+            );
+            let _ = kmsg.write().unwrap().write_all(kmsg_message.as_bytes());
+        }
+
+        #[cfg(all(feature = "android", target_os = "android"))]
+        if let Some(tag) = &self.logcat_tag {
+            // Logcat adds its own time stamp and level.
+            let text = self
+                .format_message(record, LogTimeFormat::TimeNone, &now, seq, false)
+                .replace('\0', "");
+            let text = std::ffi::CString::new(text).unwrap();
+            unsafe {
+                __android_log_write(
+                    Jlogger::logcat_priority(record.level()),
+                    tag.as_ptr(),
+                    text.as_ptr(),
+                );
+            }
+        }
+
+        #[cfg(all(feature = "windows", windows))]
+        if let Some(eventlog) = &self.eventlog {
+            // The event log adds its own time stamp and event type.
+            let text =
+                to_wide(&self.format_message(record, LogTimeFormat::TimeNone, &now, seq, false));
+            let strings = [text.as_ptr()];
+            unsafe {
+                ReportEventW(
+                    eventlog.0,
+                    Jlogger::eventlog_type(record.level()),
+                    0,
+                    0,
+                    std::ptr::null_mut(),
+                    1,
+                    0,
+                    strings.as_ptr(),
+                    std::ptr::null_mut(),
+                );
+            }
+        }
+
+        if let Some(ring) = &self.memory_ring {
+            let log_message = self.format_message(record, file_time_format, &now, seq, false);
+            ring.push(strip_ansi(&log_message).into_owned());
+        }
+
+        #[cfg(unix)]
+        if let Some(socket) = &self.unix_socket {
+            let mut socket_message =
+                self.format_message(record, file_time_format, &now, seq, false);
+            socket_message.push('\n');
+            if socket
+                .lock()
+                .unwrap()
+                .send(&strip_ansi(&socket_message))
+                .is_err()
+            {
+                self.dropped_records.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+
+        let mut log_file = match self.file_lock_mode {
+            LockMode::Block => self.log_file.write().unwrap(),
+            LockMode::TrySkip => match self.log_file.try_write() {
+                Ok(log_file) => log_file,
+                Err(TryLockError::WouldBlock) => {
+                    self.dropped_records.fetch_add(1, Ordering::Relaxed);
+                    return;
+                }
+                Err(TryLockError::Poisoned(e)) => panic!("{}", e),
+            },
+        };
+
+        if let Some(f) = log_file.as_mut() {
+            if let Some(base) = &f.hourly_base {
+                let name = hourly_log_file(base, &now);
+                if name != f.name {
+                    // Keep writing to the current file if the new one can not be opened.
+                    if let Ok(writer) = self.open_new_log_file(&name, true) {
+                        let _ = f.writer.flush();
+                        f.writer = Box::new(writer);
+                        f.name = name;
+                        self.prune_hourly_log_files(base);
+                    }
+                }
+            }
+
+            // The log files of the routes are reopened too, each checks the generation.
+            #[cfg(unix)]
+            {
+                let generation = REOPEN_GENERATION.load(Ordering::SeqCst);
+                if f.reopen_generation != generation {
+                    f.reopen_generation = generation;
+                    // Keep writing to the current file if it can not be reopened.
+                    if let Ok(writer) = open_log_file(&f.name, true) {
+                        f.writer = Box::new(writer);
+                    }
+                }
+            }
+
+            let log_message = if self.console_template.is_none()
+                && self.file_template.is_none()
+                && file_time_format == console_time_format
+            {
+                console_message
+            } else {
+                let mut log_message = match &self.file_template {
+                    Some(t) => self.render_template(t, record, file_time_format, &now, seq, false),
+                    None => self.format_message(record, file_time_format, &now, seq, false),
+                };
+                log_message.push('\n');
+                log_message
+            };
+
+            if let Some(pending) = self.file_paused.lock().unwrap().as_mut() {
+                if pending.len() < self.pause_buffer {
+                    pending.push(strip_ansi(&log_message).into_owned());
+                } else {
+                    self.dropped_records.fetch_add(1, Ordering::Relaxed);
+                }
+            } else {
+                if let Some(header) = f.header.take() {
+                    self.write_log_file(f, &header);
+                }
+                self.write_log_file(f, &strip_ansi(&log_message));
+            }
+        }
+    }
+
+    /// Syslog priority of `level` used in the kernel log.
+    fn kmsg_priority(level: log::Level) -> u8 {
+        match level {
+            log::Level::Error => 3,
+            log::Level::Warn => 4,
+            log::Level::Info => 6,
+            log::Level::Debug | log::Level::Trace => 7,
+        }
+    }
+
+    /// Android log priority of `level` used in logcat.
+    #[cfg(any(test, all(feature = "android", target_os = "android")))]
+    fn logcat_priority(level: log::Level) -> std::os::raw::c_int {
+        match level {
+            log::Level::Error => 6,
+            log::Level::Warn => 5,
+            log::Level::Info => 4,
+            log::Level::Debug => 3,
+            log::Level::Trace => 2,
+        }
+    }
+
+    /// Windows Event Log event type of `level`.
+    #[cfg(any(test, all(feature = "windows", windows)))]
+    fn eventlog_type(level: log::Level) -> u16 {
+        const EVENTLOG_ERROR_TYPE: u16 = 0x0001;
+        const EVENTLOG_WARNING_TYPE: u16 = 0x0002;
+        const EVENTLOG_INFORMATION_TYPE: u16 = 0x0004;
+
+        match level {
+            log::Level::Error => EVENTLOG_ERROR_TYPE,
+            log::Level::Warn => EVENTLOG_WARNING_TYPE,
+            log::Level::Info | log::Level::Debug | log::Level::Trace => EVENTLOG_INFORMATION_TYPE,
+        }
+    }
+
+    fn write_console(&self, console_message: &str) {
+        if self.log_console && !self.console_closed.load(Ordering::Relaxed) {
+            // Only the terminal the width is taken from is wrapped, not the console devices.
+            let console_message = match self.console_width {
+                Some(width) => std::borrow::Cow::Owned(wrap_lines(console_message, width)),
+                None => std::borrow::Cow::Borrowed(console_message),
+            };
+
+            // The reader of stderr went away (e.g. `| head`), stop writing to it but keep the
+            // other sinks working.
+            let closed = match self.console.lock().unwrap().as_mut() {
+                Some(console) => console
+                    .write_all(console_message.as_bytes())
+                    .is_err_and(|e| e.kind() == io::ErrorKind::BrokenPipe),
+                // eprint! is captured by the test harness, unlike writing to io::stderr(), but
+                // panics when the write fails, e.g. on a closed pipe.
+                None => std::panic::catch_unwind(|| eprint!("{}", console_message)).is_err(),
+            };
+            if closed {
+                self.console_closed.store(true, Ordering::Relaxed);
+            }
+        }
+
+        for d in &self.console_devices {
+            // A console device going away should not affect the other sinks.
+            let _ = d.write().unwrap().write_all(console_message.as_bytes());
+        }
+    }
+
+    /// Write `buf` to the log file, retrying a failed write as configured by
+    /// [`JloggerBuilder::write_retry`]. An interrupted write is retried immediately.
+    fn write_with_retry(&self, f: &mut LogFile, buf: &[u8]) -> io::Result<()> {
+        let (attempts, backoff) = self.write_retry;
+        let mut written = 0;
+        let mut failures = 0;
+
+        while written < buf.len() {
+            match f.writer.write(&buf[written..]) {
+                Ok(0) => return Err(io::ErrorKind::WriteZero.into()),
+                Ok(n) => written += n,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                // Retrying a FIFO which is full would block the logging thread.
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => return Err(e),
+                Err(_) if failures < attempts => {
+                    std::thread::sleep(backoff * 2u32.saturating_pow(failures));
+                    failures += 1;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+
+        Ok(())
+    }
+
+    fn write_log_file(&self, f: &mut LogFile, log_message: &str) {
+        match self.write_with_retry(f, log_message.as_bytes()) {
+            Ok(()) => {
+                self.bytes_written
+                    .fetch_add(log_message.len() as u64, Ordering::Relaxed);
+                if let Some(interval) = self.fsync_interval {
+                    if f.last_sync.map(|t| t.elapsed() >= interval).unwrap_or(true) {
+                        let _ = f.writer.sync_data();
+                        f.last_sync = Some(Instant::now());
+                    }
+                }
+            }
+            // The log file is a FIFO whose reader is not keeping up or has gone away,
+            // drop the record rather than blocking or failing the logging thread.
+            Err(e)
+                if e.kind() == io::ErrorKind::WouldBlock
+                    || e.kind() == io::ErrorKind::BrokenPipe =>
+            {
+                self.dropped_records.fetch_add(1, Ordering::Relaxed);
+            }
+            Err(e) if e.kind() == io::ErrorKind::StorageFull => match self.disk_full_policy {
+                DiskFullPolicy::Drop => {
+                    self.dropped_records.fetch_add(1, Ordering::Relaxed);
+                }
+                DiskFullPolicy::Console => self.write_console(log_message),
+                DiskFullPolicy::Rotate => {
+                    let written = Jlogger::rotate_log_file(&f.name).and_then(|w| {
+                        f.writer = w;
+                        f.writer.write_all(log_message.as_bytes())
+                    });
+
+                    match written {
+                        Ok(()) => {
+                            self.bytes_written
+                                .fetch_add(log_message.len() as u64, Ordering::Relaxed);
+                        }
+                        Err(_) => {
+                            self.dropped_records.fetch_add(1, Ordering::Relaxed);
+                        }
+                    }
+                }
+            },
+            Err(_) => {
+                self.dropped_records.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    }
+
+    /// Remove the old hourly log files of `base`, see [`prune_hourly_log_files`].
+    fn prune_hourly_log_files(&self, base: &str) {
+        prune_hourly_log_files(base, self.max_hourly_files, self.max_hourly_age);
+    }
+
+    /// Open the log file `name` replacing the current one, locked if
+    /// [`JloggerBuilder::exclusive_lock`] is enabled.
+    fn open_new_log_file(&self, name: &str, append: bool) -> io::Result<File> {
+        #[cfg(unix)]
+        if self.exclusive_lock {
+            return open_locked_log_file(name, append);
+        }
+
+        open_log_file(name, append)
+    }
+
+    /// Move the log file to `<log file>.1`, replacing the previously rotated one, and return a
+    /// new log file.
+    fn rotate_log_file(log_file: &str) -> io::Result<LogWriter> {
+        fs::rename(log_file, format!("{}.1", log_file))?;
+        Ok(Box::new(open_log_file(log_file, false)?))
+    }
+
+    /// Write `record` with its message replaced by `args`.
+    fn write_record_with_args(&self, record: &Record, args: std::fmt::Arguments) {
+        self.write_record(&Jlogger::record_with_args(record, args));
+    }
+
+    /// Copy of `record` with its message replaced by `args`.
+    fn record_with_args<'a>(record: &'a Record, args: std::fmt::Arguments<'a>) -> Record<'a> {
+        Record::builder()
+            .metadata(record.metadata().clone())
+            .module_path(record.module_path())
+            .file(record.file())
+            .line(record.line())
+            .key_values(record.key_values())
+            .args(args)
+            .build()
+    }
+
+    /// Replace the configured secrets in `message` with `***`.
+    /// Returns `None` if there is nothing to redact.
+    fn redact(&self, message: &str) -> Option<String> {
+        if !self.redactions.iter().any(|r| message.contains(r.as_str())) {
+            return None;
+        }
+
+        let mut message = message.to_string();
+        for r in &self.redactions {
+            message = message.replace(r.as_str(), "***");
+        }
+
+        Some(message)
+    }
+
+    /// Write each line of a multi-line message as a separate log message, keeping the location
+    /// prefix added by the jlogger macros.
+    fn write_multiline_record(&self, record: &Record) {
+        let message = record.args().to_string();
+        let body = Jlogger::message_body(record);
+        let location = &message[..message.len() - body.len()];
+
+        for line in body.lines() {
+            self.write_record_with_args(record, format_args!("{}{}", location, line));
+        }
+    }
+
+    /// Write a long message as several lines, each one ends with a correlation id shared by all
+    /// the lines and all but the last one are marked with `(cont.)`.
+    fn write_split_record(&self, record: &Record, limit: usize) {
+        let id = self.split_id.fetch_add(1, Ordering::Relaxed) + 1;
+        let message = record.args().to_string();
+        let chunks = split_message(&message, limit);
+
+        for (i, chunk) in chunks.iter().enumerate() {
+            let cont = if i + 1 < chunks.len() { " (cont.)" } else { "" };
+            self.write_record_with_args(record, format_args!("{}{} [split:{}]", chunk, cont, id));
+        }
+    }
+
+    /// Output a log message which passed the filter.
+    fn log_record(&self, record: &Record) {
+        self.level_counts[record.level() as usize - 1].fetch_add(1, Ordering::Relaxed);
+        if self.heartbeat.is_some() {
+            self.last_logged
+                .store(self.clock_elapsed().as_millis() as u64, Ordering::Relaxed);
+        }
+
+        if self.metadata_only {
+            // The message is replaced before anything formats it, so that its arguments
+            // are never evaluated.
+            let location = match (record.file(), record.line()) {
+                (Some(file), Some(line)) => match self.path_components {
+                    Some(n) => format!("{}-{}", shorten_path(file, n), line),
+                    None => format!("{}-{}", file, line),
+                },
+                _ => "?".to_string(),
+            };
+            return self.dispatch_record(&Jlogger::record_with_args(
+                record,
+                format_args!("{}", location),
+            ));
+        }
+
+        let mut message = None;
+        if !self.redactions.is_empty() {
+            message = self.redact(&record.args().to_string());
+        }
+
+        if self.normalize_newlines {
+            let text = message.clone().unwrap_or_else(|| record.args().to_string());
+            let trimmed = text.trim_end_matches(['\r', '\n']);
+            if trimmed.len() < text.len() {
+                message = Some(trimmed.to_string());
+            }
+        }
+
+        match message {
+            Some(message) => self.dispatch_record(&Jlogger::record_with_args(
+                record,
+                format_args!("{}", message),
+            )),
+            None => self.dispatch_record(record),
+        }
+    }
+
+    /// Whether `target` is muted, see [`JloggerBuilder::mute_target`].
+    fn muted(&self, target: &str) -> bool {
+        self.muted_targets.iter().any(|t| target_matches(target, t))
+    }
+
+    /// Level of the most specific [`JloggerBuilder::target_level`] matching `target`.
+    fn target_level(&self, target: &str) -> Option<LevelFilter> {
+        self.target_levels
+            .iter()
+            .filter(|(t, _)| target_matches(target, t))
+            .max_by_key(|(t, _)| t.len())
+            .map(|(_, l)| *l)
+    }
+
+    /// Level of the targets without a [`JloggerBuilder::target_level`].
+    fn global_level(&self) -> LevelFilter {
+        match std::env::var("JLOGGER_LEVEL") {
+            Ok(l) => parse_level_filter(&l).unwrap_or(LevelFilter::Off),
+            Err(_) => self.max_level,
+        }
+    }
+
+    /// Whether a log message filtered out may be held back by a [`ConditionalBufferScope`]: only
+    /// the global level filters it out, not a muted target, a target level or a filter.
+    fn conditional_bufferable(&self, metadata: &Metadata) -> bool {
+        !self.muted(metadata.target())
+            && self.target_level(metadata.target()).is_none()
+            && self.filters.iter().all(|f| f(metadata))
+    }
+
+    /// Output a log message held back by a [`ConditionalBufferScope`] with its original time,
+    /// `args` is its message including the location prefix added by the jlogger macros.
+    fn log_buffered(&self, buffered: &LogRecord, args: &str) {
+        RECORD_TIME.with(|t| t.set(Some(buffered.timestamp)));
+        self.log_record(
+            &Record::builder()
+                .level(buffered.level)
+                .target(&buffered.target)
+                .file(buffered.file.as_deref())
+                .line(buffered.line)
+                .args(format_args!("{}", args))
+                .build(),
+        );
+        RECORD_TIME.with(|t| t.set(None));
+    }
+
+    /// Logger of the most specific route matching `target`, see [`JloggerBuilder::route`].
+    fn route(&self, target: &str) -> Option<&Jlogger> {
+        self.routes
+            .iter()
+            .filter(|(t, _)| target_matches(target, t))
+            .max_by_key(|(t, _)| t.len())
+            .map(|(_, logger)| logger)
+    }
+
+    /// Output a summary of each burst closed, see [`JloggerBuilder::burst_summary`].
+    fn write_burst_summaries(&self, all: bool) {
+        let closed = match &self.burst {
+            Some(burst) => burst.lock().unwrap().close(self.clock.now(), all),
+            None => return,
+        };
+
+        for burst in closed {
+            self.write_record(
+                &Record::builder()
+                    .level(burst.level)
+                    .target(&burst.target)
+                    .args(format_args!(
+                        "(suppressed {} duplicates of \"{}\", last: {})",
+                        burst.suppressed, burst.body, burst.last
+                    ))
+                    .build(),
+            );
+        }
+    }
+
+    /// Output a summary of each message whose dedup window closed with suppressed duplicates,
+    /// or of all of them if `all` is true, see [`JloggerBuilder::dedup_by_hash`].
+    fn write_dedup_summaries(&self, all: bool) {
+        let closed = match &self.dedup {
+            Some(dedup) => dedup.lock().unwrap().close(self.clock.now(), all),
+            None => return,
+        };
+
+        for entry in closed {
+            self.write_dedup_summary(&entry);
+        }
+    }
+
+    fn write_dedup_summary(&self, entry: &DedupEntry) {
+        self.write_record(
+            &Record::builder()
+                .level(entry.level)
+                .target(&entry.target)
+                .args(format_args!(
+                    "(suppressed {} duplicates of \"{}\")",
+                    entry.suppressed, entry.body
+                ))
+                .build(),
+        );
+    }
+
+    /// Output a log message which passed the filter to the sinks.
+    fn dispatch_record(&self, record: &Record) {
+        if let Some(burst) = &self.burst {
+            self.write_burst_summaries(false);
+
+            let body = Jlogger::message_body(record);
+            if !burst.lock().unwrap().check(&body, record, self.clock.now()) {
+                return;
+            }
+        }
+
+        if let Some(dedup) = &self.dedup {
+            self.write_dedup_summaries(false);
+
+            let body = Jlogger::message_body(record);
+            let (first, evicted) = dedup.lock().unwrap().check(&body, record, self.clock.now());
+            if let Some(entry) = evicted {
+                self.write_dedup_summary(&entry);
+            }
+            if !first {
+                return;
+            }
+        }
+
+        if let Some(sender) = &self.record_channel {
+            // The receiver going away should not affect the other sinks.
+            let _ = sender.send(LogRecord::new(record, self.now()));
+        }
+
+        if self.backtrace_on_error && record.level() == log::Level::Error {
+            let backtrace = std::backtrace::Backtrace::capture();
+            if backtrace.status() == std::backtrace::BacktraceStatus::Captured {
+                return self.output_record(&Jlogger::record_with_args(
+                    record,
+                    format_args!("{}\nstack backtrace:\n{}", record.args(), backtrace),
+                ));
+            }
+        }
+
+        self.output_record(record);
+    }
+
+    /// Output a log message as one or several lines.
+    fn output_record(&self, record: &Record) {
+        let message = record.args().to_string();
+        if self.prefix_multiline && message.contains('\n') {
+            self.write_multiline_record(record);
+        } else {
+            match self.max_line_len_split {
+                Some(limit) if message.len() > limit => self.write_split_record(record, limit),
+                _ => self.write_record(record),
+            }
+        }
+    }
+
+    fn log_banner(&self) {
+        self.log(
+            &Record::builder()
+                .level(log::Level::Info)
+                .target(module_path!())
+                .args(format_args!("{}", self.banner()))
+                .build(),
+        );
+    }
+
+    fn log_stats(&self) {
+        let count =
+            |level: log::Level| self.level_counts[level as usize - 1].load(Ordering::Relaxed);
+
+        self.log(
+            &Record::builder()
+                .level(log::Level::Info)
+                .target(module_path!())
+                .args(format_args!(
+                    "jlogger stats: error={} warn={} info={} debug={} trace={} dropped={} bytes={}",
+                    count(log::Level::Error),
+                    count(log::Level::Warn),
+                    count(log::Level::Info),
+                    count(log::Level::Debug),
+                    count(log::Level::Trace),
+                    self.dropped_records.load(Ordering::Relaxed),
+                    self.bytes_written.load(Ordering::Relaxed)
+                ))
+                .build(),
+        );
+    }
+
+    /// Time elapsed since the logger was created, according to its [`Clock`].
+    fn clock_elapsed(&self) -> Duration {
+        (self.clock.now() - self.start_time)
+            .to_std()
+            .unwrap_or_default()
+    }
+
+    /// Log the statistics if `interval` elapsed since `last`, the time they were last logged.
+    fn stats_tick(&self, interval: Duration, last: &mut Duration) {
+        let elapsed = self.clock_elapsed();
+
+        if elapsed.saturating_sub(*last) >= interval {
+            self.log_stats();
+            *last = elapsed;
+        }
+    }
+
+    /// Start the thread logging the statistics, see [`JloggerBuilder::stats_interval`].
+    /// The thread ends once the [`JloggerGuard`] is dropped.
+    fn start_stats(&'static self) {
+        if let Some(interval) = self.stats_interval {
+            std::thread::Builder::new()
+                .name("jlogger-stats".to_string())
+                .spawn(move || {
+                    let mut last = Duration::ZERO;
+
+                    loop {
+                        std::thread::sleep(interval);
+                        if self.stopped.load(Ordering::Relaxed) {
+                            break;
+                        }
+                        self.stats_tick(interval, &mut last);
+                    }
+                })
+                .unwrap();
+        }
+    }
+
+    /// Flush the log files of this logger and of its routes to the storage device.
+    fn sync_log_files(&self) {
+        for (_, logger) in &self.routes {
+            logger.sync_log_files();
+        }
+
+        if let Some(f) = self.log_file.write().unwrap().as_mut() {
+            let _ = f.writer.sync_data();
+            f.last_sync = Some(Instant::now());
+        }
+    }
+
+    /// Log a heartbeat if nothing was logged for `interval`, returns how long to wait until the
+    /// next check.
+    fn heartbeat_tick(&self, interval: Duration) -> Duration {
+        let last_logged = Duration::from_millis(self.last_logged.load(Ordering::Relaxed));
+        let idle = self.clock_elapsed().saturating_sub(last_logged);
+
+        if idle >= interval {
+            self.log(
+                &Record::builder()
+                    .level(log::Level::Info)
+                    .target(module_path!())
+                    .args(format_args!("heartbeat"))
+                    .build(),
+            );
+            // The heartbeat may be filtered out, do not retry it immediately.
+            interval
+        } else {
+            interval - idle
+        }
+    }
+
+    /// Start the thread outputting the summaries of the dedup and burst windows which closed, see
+    /// [`JloggerBuilder::dedup_by_hash`] and [`JloggerBuilder::burst_summary`].
+    /// The thread ends once the [`JloggerGuard`] is dropped.
+    fn start_summaries(&'static self) {
+        for (_, logger) in &self.routes {
+            logger.start_summaries();
+        }
+
+        let dedup_window = self.dedup.as_ref().map(|d| d.lock().unwrap().window);
+        let burst_window = self.burst.as_ref().map(|b| b.lock().unwrap().window);
+        if let Some(window) = dedup_window.into_iter().chain(burst_window).min() {
+            std::thread::Builder::new()
+                .name("jlogger-summary".to_string())
+                .spawn(move || loop {
+                    std::thread::sleep(window);
+                    if self.stopped.load(Ordering::Relaxed) {
+                        break;
+                    }
+                    self.write_burst_summaries(false);
+                    self.write_dedup_summaries(false);
+                })
+                .unwrap();
+        }
+    }
+
+    /// Start the thread logging a heartbeat when nothing was logged for a while, see
+    /// [`JloggerBuilder::heartbeat`]. The thread ends once the [`JloggerGuard`] is dropped.
+    fn start_heartbeat(&'static self) {
+        if let Some(interval) = self.heartbeat {
+            std::thread::Builder::new()
+                .name("jlogger-heartbeat".to_string())
+                .spawn(move || {
+                    while !self.stopped.load(Ordering::Relaxed) {
+                        std::thread::sleep(self.heartbeat_tick(interval));
+                    }
+                })
+                .unwrap();
+        }
+    }
+}
+
+impl Log for Jlogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        if let Some(logger) = self.route(metadata.target()) {
+            return logger.enabled(metadata);
+        }
+
+        if self.muted(metadata.target()) {
+            return false;
+        }
+
+        let level = self
+            .target_level(metadata.target())
+            .unwrap_or_else(|| self.global_level());
+
+        metadata.level() <= level && self.filters.iter().all(|f| f(metadata))
+    }
+
+    fn log(&self, record: &Record) {
+        if let Some(logger) = self.route(record.target()) {
+            return logger.log(record);
+        }
+
+        if self.enabled(record.metadata()) {
+            if record.level() == log::Level::Error {
+                for (buffered, args) in trip_conditional_buffer() {
+                    self.log_buffered(&buffered, &args);
+                }
+            }
+            self.log_record(record);
+        } else if record.level() >= log::Level::Debug
+            && self.conditional_bufferable(record.metadata())
+            && capture_conditional_buffer(record, self.clock.now())
+        {
+            self.log_record(record);
+        }
+    }
+
+    fn flush(&self) {
+        for (_, logger) in &self.routes {
+            logger.flush();
+        }
+
+        self.write_burst_summaries(true);
+        self.write_dedup_summaries(true);
+
+        if let Some(batch) = &self.console_batch {
+            let mut batch = batch.lock().unwrap();
+            if !batch.pending.is_empty() {
+                self.write_console(&std::mem::take(&mut batch.pending));
+            }
+        }
+
+        match self.console.lock().unwrap().as_mut() {
+            Some(console) => {
+                let _ = console.flush();
+            }
+            None => {
+                let _ = io::stderr().flush();
+            }
+        }
+        if let Some(f) = self.log_file.write().unwrap().as_mut() {
+            let _ = f.writer.flush();
+        }
+    }
+}
+
+pub struct JloggerBuilder {
+    max_level: LevelFilter,
+    log_console: bool,
+    log_file_name: Option<String>,
+    log_file_append: bool,
+    session_separator: bool,
+    lazy_file: bool,
+    rotate_hourly: bool,
+    max_hourly_files: Option<usize>,
+    max_hourly_age: Option<Duration>,
+    #[cfg(unix)]
+    exclusive_lock: bool,
+    /// Log file opened by try_build() and its path, locked if exclusive_lock is enabled.
+    opened_file: Option<(String, File)>,
+    fsync_interval: Option<Duration>,
+    disk_full_policy: DiskFullPolicy,
+    file_lock_mode: LockMode,
+    write_retry: (u32, Duration),
+    pause_buffer: usize,
+    #[cfg(unix)]
+    reopen_signal: Option<std::os::raw::c_int>,
+    log_runtime: bool,
+    log_thread_id: bool,
+    log_seq: bool,
+    log_banner: bool,
+    stats_interval: Option<Duration>,
+    heartbeat: Option<Duration>,
+    empty_message: Option<String>,
+    time_format: LogTimeFormat,
+    console_time_format: Option<LogTimeFormat>,
+    file_time_format: Option<LogTimeFormat>,
+    level_style: LevelStyle,
+    level_names: Vec<(log::Level, String)>,
+    field_order: Vec<LogField>,
+    field_separator: String,
+    console_template: Option<String>,
+    file_template: Option<String>,
+    dedup_window: Option<Duration>,
+    burst_window: Option<Duration>,
+    console: Option<Box<dyn Write + Send>>,
+    console_devices: Vec<String>,
+    console_wrap: bool,
+    /// Width console messages are wrapped at, the terminal width is used if not set.
+    console_width: Option<usize>,
+    color_targets: bool,
+    console_rate_limit: Option<u32>,
+    console_batch: Option<Duration>,
+    log_kmsg: bool,
+    kmsg_path: String,
+    memory_ring: Option<usize>,
+    #[cfg(unix)]
+    unix_socket: Option<String>,
+    #[cfg(all(feature = "android", target_os = "android"))]
+    logcat_tag: Option<String>,
+    #[cfg(all(feature = "windows", windows))]
+    eventlog_source: Option<String>,
+    record_channel: Option<Sender<LogRecord>>,
+    redactions: Vec<String>,
+    normalize_newlines: bool,
+    path_components: Option<usize>,
+    metadata_only: bool,
+    muted_targets: Vec<String>,
+    filters: Vec<Filter>,
+    target_levels: Vec<(String, LevelFilter)>,
+    routes: Vec<(String, JloggerBuilder)>,
+    target_versions: Vec<(String, String)>,
+    severity_map: Option<SeverityMap>,
+    max_line_len_split: Option<usize>,
+    prefix_multiline: bool,
+    backtrace_on_error: bool,
+    clock: Box<dyn Clock>,
+    timestamp_base: TimeStampBase,
+    /// Reader of the system boot time, replaced in tests.
+    boot_time: fn() -> Option<i64>,
+    /// Reader of the time elapsed since the process started, replaced in tests.
+    process_age: fn() -> Option<Duration>,
+    log_utc_offset: bool,
+    pid_file: Option<String>,
+    log_cwd: bool,
+    version: Option<String>,
+    strict_env: bool,
+}
+
+impl Default for JloggerBuilder {
+    fn default() -> Self {
+        JloggerBuilder::new()
+    }
+}
+
+impl JloggerBuilder {
+    /// Create a new JloggerBuilder which is used to build a Jlogger.
+    ///
+    /// # Examples
+    /// ```
+    ///     use log::LevelFilter;
+    ///     use jlogger::{JloggerBuilder, LogTimeFormat};
+    ///
+    ///     let _guard = JloggerBuilder::new()
+    ///        .max_level(LevelFilter::Debug)
+    ///        .log_console(true)
+    ///        .log_time(LogTimeFormat::TimeStamp)
+    ///        .log_file(Some("/tmp/my_log.log"), false)
+    ///        .build();
+    ///
+    /// ```
+    pub fn new() -> Self {
+        JloggerBuilder {
+            max_level: LevelFilter::Info,
+            log_console: true,
+            log_file_name: None,
+            log_file_append: false,
+            session_separator: false,
+            lazy_file: false,
+            rotate_hourly: false,
+            max_hourly_files: None,
+            max_hourly_age: None,
+            #[cfg(unix)]
+            exclusive_lock: false,
+            opened_file: None,
+            fsync_interval: None,
+            disk_full_policy: DiskFullPolicy::Drop,
+            file_lock_mode: LockMode::Block,
+            write_retry: (0, Duration::ZERO),
+            pause_buffer: 0,
+            #[cfg(unix)]
+            reopen_signal: None,
+            log_runtime: false,
+            log_thread_id: false,
+            log_seq: false,
+            log_banner: false,
+            stats_interval: None,
+            heartbeat: None,
+            empty_message: None,
+            time_format: LogTimeFormat::TimeNone,
+            console_time_format: None,
+            file_time_format: None,
+            level_style: LevelStyle::Full,
+            level_names: Vec::new(),
+            field_order: vec![
+                LogField::Seq,
+                LogField::Timestamp,
+                LogField::Level,
+                LogField::Runtime,
+                LogField::Version,
+                LogField::Severity,
+                LogField::Message,
+            ],
+            field_separator: " ".to_string(),
+            console_template: None,
+            file_template: None,
+            dedup_window: None,
+            burst_window: None,
+            console: None,
+            console_devices: Vec::new(),
+            console_wrap: false,
+            console_width: None,
+            color_targets: false,
+            console_rate_limit: None,
+            console_batch: None,
+            log_kmsg: false,
+            kmsg_path: "/dev/kmsg".to_string(),
+            memory_ring: None,
+            #[cfg(unix)]
+            unix_socket: None,
+            #[cfg(all(feature = "android", target_os = "android"))]
+            logcat_tag: None,
+            #[cfg(all(feature = "windows", windows))]
+            eventlog_source: None,
+            record_channel: None,
+            redactions: Vec::new(),
+            normalize_newlines: false,
+            path_components: None,
+            metadata_only: false,
+            muted_targets: Vec::new(),
+            filters: Vec::new(),
+            target_levels: Vec::new(),
+            routes: Vec::new(),
+            target_versions: Vec::new(),
+            severity_map: None,
+            max_line_len_split: None,
+            prefix_multiline: false,
+            backtrace_on_error: false,
+            clock: Box::new(SystemClock),
+            timestamp_base: TimeStampBase::Boot,
+            boot_time: read_boot_time,
+            process_age: read_process_age,
+            log_utc_offset: false,
+            pid_file: None,
+            version: None,
+            log_cwd: false,
+            strict_env: false,
+        }
+    }
+
+    /// Apply a set of settings at once.
+    /// Settings can still be changed by calling the corresponding builder methods afterwards.
+    ///
+    /// ```
+    ///     use jlogger::{JloggerBuilder, Preset};
+    ///
+    ///     let _guard = JloggerBuilder::new()
+    ///         .preset(Preset::DevFriendly)
+    ///         .log_file(Some("/tmp/my_log.log"), false)
+    ///         .build();
+    /// ```
+    pub fn preset(self, preset: Preset) -> Self {
+        match preset {
+            Preset::DevFriendly => self
+                .max_level(LevelFilter::Debug)
+                .log_console(true)
+                .log_runtime(true)
+                .console_time(LogTimeFormat::TimeStamp)
+                .file_time(LogTimeFormat::TimeLocal)
+                .level_style(LevelStyle::Full),
+            Preset::Production => self
+                .max_level(LevelFilter::Info)
+                .log_console(true)
+                .log_runtime(true)
+                .log_time(LogTimeFormat::TimeLocal)
+                .level_style(LevelStyle::Full)
+                .log_banner(true),
+            Preset::Compact => self
+                .log_runtime(false)
+                .log_time(LogTimeFormat::TimeNone)
+                .level_style(LevelStyle::Short),
+        }
+    }
+
+    /// Builder configured by environment variables, see [`init_from_env`] for the variables.
+    /// Settings can still be changed by calling the builder methods afterwards.
+    pub fn from_env() -> Result<Self, JloggerError> {
+        let var = |name: &'static str| std::env::var(name).ok().filter(|v| !v.is_empty());
+        let invalid = |name: &'static str, value: String| JloggerError::InvalidEnv { name, value };
+
+        let mut builder = match var("JLOGGER_FORMAT") {
+            None => JloggerBuilder::new()
+                .log_runtime(true)
+                .log_time(LogTimeFormat::TimeLocal),
+            Some(format) => match format.to_ascii_lowercase().as_str() {
+                "default" => JloggerBuilder::new()
+                    .log_runtime(true)
+                    .log_time(LogTimeFormat::TimeLocal),
+                "dev" => JloggerBuilder::new().preset(Preset::DevFriendly),
+                "production" => JloggerBuilder::new().preset(Preset::Production),
+                "compact" => JloggerBuilder::new().preset(Preset::Compact),
+                _ => return Err(invalid("JLOGGER_FORMAT", format)),
+            },
+        };
+
+        if let Some(level) = var("JLOGGER_LEVEL") {
+            match parse_level_filter(&level) {
+                Some(level) => builder = builder.max_level(level),
+                None => return Err(invalid("JLOGGER_LEVEL", level)),
+            }
+        }
+
+        if let Some(file) = var("JLOGGER_FILE") {
+            builder = builder.log_file(Some(&file), true);
+        }
+
+        if let Some(time) = var("JLOGGER_TIME") {
+            let time_format = match time.to_ascii_lowercase().as_str() {
+                "stamp" => LogTimeFormat::TimeStamp,
+                "local" => LogTimeFormat::TimeLocal,
+                "none" => LogTimeFormat::TimeNone,
+                "relative" => LogTimeFormat::Relative,
+                "epoch_nanos" => LogTimeFormat::EpochNanos,
+                "local_uptime" => LogTimeFormat::LocalWithUptime,
+                _ => return Err(invalid("JLOGGER_TIME", time)),
+            };
+            builder = builder.log_time(time_format);
+        }
+
+        Ok(builder)
+    }
+
+    /// Set the max level to be outputted.
+    /// Log messages with a level below it will not be outputted.
+    /// At runtime, the log level can be filtered though "JLOGGER_LEVEL" environment variable.
+    pub fn max_level(mut self, max_level: LevelFilter) -> Self {
+        self.max_level = max_level;
+        self
+    }
+
+    /// Set the max level from a `-v` count of a command line program.
+    ///
+    /// | verbosity | Level |
+    /// |-----------|-------|
+    /// | 0         | WARN  |
+    /// | 1         | INFO  |
+    /// | 2         | DEBUG |
+    /// | 3+        | TRACE |
+    ///
+    /// ```
+    ///     use clap::Parser;
+    ///     use jlogger::JloggerBuilder;
+    ///
+    ///     #[derive(Parser)]
+    ///     struct Cli {
+    ///         #[clap(short, action = clap::ArgAction::Count)]
+    ///         verbose: u8,
+    ///     }
+    ///
+    ///     let cli = Cli::parse_from(["prog", "-vv"]);
+    ///     let _guard = JloggerBuilder::new().verbosity(cli.verbose).build();
+    /// ```
+    pub fn verbosity(mut self, verbosity: u8) -> Self {
+        self.max_level = match verbosity {
+            0 => LevelFilter::Warn,
+            1 => LevelFilter::Info,
+            2 => LevelFilter::Debug,
+            _ => LevelFilter::Trace,
+        };
+        self
+    }
+
+    /// Set the max level from a `-q` count of a command line program, 0 leaves the max level
+    /// unchanged.
+    ///
+    /// | quiet | Level |
+    /// |-------|-------|
+    /// | 1     | ERROR |
+    /// | 2+    | OFF   |
+    pub fn quiet(mut self, quiet: u8) -> Self {
+        match quiet {
+            0 => {}
+            1 => self.max_level = LevelFilter::Error,
+            _ => self.max_level = LevelFilter::Off,
+        }
+        self
+    }
+
+    /// Drop log messages whose target is `target` or one of its sub modules, e.g. to quiet a
+    /// noisy dependency. Can be called multiple times.
+    ///
+    /// ```
+    ///     use jlogger::JloggerBuilder;
+    ///
+    ///     // Drops "hyper" and "hyper::client", but not "hyperlocal".
+    ///     let _guard = JloggerBuilder::new().mute_target("hyper").mute_target("h2").build();
+    /// ```
+    pub fn mute_target(mut self, target: &str) -> Self {
+        self.muted_targets.push(target.to_string());
+        self
+    }
+
+    /// Drop log messages for which `filter` returns false, for rules which can not be expressed
+    /// with the level and the target settings. It is only called for log messages passing the
+    /// level. Can be called multiple times, a log message has to pass all the filters.
+    ///
+    /// ```
+    ///     use jlogger::JloggerBuilder;
+    ///
+    ///     // Only WARN and above from the database modules.
+    ///     let _guard = JloggerBuilder::new()
+    ///         .filter(|m| !m.target().contains("::db") || m.level() <= log::Level::Warn)
+    ///         .build();
+    /// ```
+    pub fn filter(mut self, filter: impl Fn(&Metadata) -> bool + Send + Sync + 'static) -> Self {
+        self.filters.push(Box::new(filter));
+        self
+    }
+
+    /// Set the max level of log messages whose target is `target` or one of its sub modules,
+    /// overriding [`max_level`](Self::max_level) and the "JLOGGER_LEVEL" environment variable.
+    /// Can be called multiple times, the most specific target is used.
+    ///
+    /// ```
+    ///     use jlogger::JloggerBuilder;
+    ///     use log::LevelFilter;
+    ///
+    ///     let _guard = JloggerBuilder::new()
+    ///         .max_level(LevelFilter::Info)
+    ///         .target_level("mycrate::net", LevelFilter::Debug)
+    ///         .build();
+    /// ```
+    pub fn target_level(mut self, target: &str, level: LevelFilter) -> Self {
+        self.target_levels.push((target.to_string(), level));
+        self
+    }
+
+    /// Output log messages whose target is `target` or one of its sub modules with a separate
+    /// logger configured by `builder` instead of this one, e.g. to keep an audit log apart from
+    /// the application log. Can be called multiple times, the most specific target is used.
+    ///
+    /// Routed log messages are filtered and outputted only by the routed logger, with its own
+    /// level, sinks and format. The settings applying to the installed logger as a whole (e.g.
+    /// [`log_banner`](Self::log_banner), [`pid_file`](Self::pid_file),
+    /// [`stats_interval`](Self::stats_interval), [`heartbeat`](Self::heartbeat)) are ignored for
+    /// `builder`.
+    ///
+    /// ```
+    ///     use jlogger::JloggerBuilder;
+    ///
+    ///     // log::info!(target: "audit", "user {} logged in", user) only goes to audit.log.
+    ///     let _guard = JloggerBuilder::new()
+    ///         .route(
+    ///             "audit",
+    ///             JloggerBuilder::new()
+    ///                 .log_console(false)
+    ///                 .log_file(Some("/tmp/audit.log"), true),
+    ///         )
+    ///         .build();
+    /// ```
+    pub fn route(mut self, target: &str, builder: JloggerBuilder) -> Self {
+        self.routes.push((target.to_string(), builder));
+        self
+    }
+
+    /// Add a severity class to log messages for security tooling (SIEM), see
+    /// [`LogField::Severity`]. Log messages whose target is `audit` or `security` (or one of
+    /// their sub modules) are classed as such, the others as `operational`, unless changed by
+    /// [`severity_class`](Self::severity_class) and
+    /// [`level_severity_class`](Self::level_severity_class).
+    /// Default is false.
+    ///
+    /// >WARN  severity=security : login failed for root
+    pub fn severity_map(mut self, severity_map: bool) -> Self {
+        self.severity_map = if severity_map {
+            Some(self.severity_map.unwrap_or_default())
+        } else {
+            None
+        };
+        self
+    }
+
+    /// Class log messages whose target is `target` or one of its sub modules as `class`,
+    /// enabling [`severity_map`](Self::severity_map). Can be called multiple times, the most
+    /// specific target is used. Takes precedence over
+    /// [`level_severity_class`](Self::level_severity_class).
+    pub fn severity_class(mut self, target: &str, class: &str) -> Self {
+        let map = self.severity_map.get_or_insert_with(SeverityMap::default);
+        map.targets.retain(|(t, _)| t != target);
+        map.targets.push((target.to_string(), class.to_string()));
+        self
+    }
+
+    /// Class log messages of `level` as `class` if their target has no class, enabling
+    /// [`severity_map`](Self::severity_map).
+    pub fn level_severity_class(mut self, level: log::Level, class: &str) -> Self {
+        let map = self.severity_map.get_or_insert_with(SeverityMap::default);
+        map.levels.retain(|(l, _)| *l != level);
+        map.levels.push((level, class.to_string()));
+        self
+    }
+
+    /// Show `version` in log messages whose target is `target` or one of its sub modules, e.g.
+    /// to tell which version of a crate in a workspace logged. Can be called multiple times,
+    /// the most specific target is used.
+    ///
+    /// >INFO  mycrate@1.2.3 : this is info
+    pub fn target_version(mut self, target: &str, version: &str) -> Self {
+        self.target_versions
+            .push((target.to_string(), version.to_string()));
+        self
+    }
+
+    /// If enabled, log message will be printed to the console (stderr).
+    /// If stderr is closed by its reader, e.g. when piped to `head`, log messages are no longer
+    /// printed to it while the other outputs keep working.
+    /// Default is true.
+    pub fn log_console(mut self, log_console: bool) -> Self {
+        self.log_console = log_console;
+        self
+    }
+
+    /// Log file name.
+    /// If specified, log message will be outputted to it.
+    /// If append is true and the log file exists, new messages well be appended to the end of the
+    /// file. Otherwise, a new log file will be created.
+    /// If the log file is a FIFO (Unix only), it is opened in non-blocking write mode and log
+    /// messages are dropped when the reader can not accept them.
+    /// ANSI escape sequences (e.g. colors) in log messages are removed from the log file.
+    ///
+    /// Same as calling [`log_file_path`](Self::log_file_path) and
+    /// [`log_file_append`](Self::log_file_append), `None` leaves the log file settings unchanged.
+    pub fn log_file(mut self, log_file: Option<&str>, append: bool) -> Self {
+        if let Some(log_file) = log_file {
+            self = self.log_file_path(log_file).log_file_append(append);
+        }
+
+        self
+    }
+
+    /// Log file name, see [`log_file`](Self::log_file).
+    /// If called more than once or together with [`log_file`](Self::log_file), the last call
+    /// wins.
+    pub fn log_file_path(mut self, log_file: &str) -> Self {
+        self.log_file_name = Some(log_file.to_string());
+        self
+    }
+
+    /// Whether to append to an existing log file, see [`log_file`](Self::log_file).
+    /// Default is false. If called more than once or together with [`log_file`](Self::log_file),
+    /// the last call wins.
+    pub fn log_file_append(mut self, append: bool) -> Self {
+        self.log_file_append = append;
+        self
+    }
+
+    /// When appending to the log file, write a line marking the start of this run before the
+    /// first log message, so that the runs sharing a log file can be told apart.
+    /// Default is false.
+    ///
+    /// >===== new session 2022-05-17 13:00:03 pid=4242 =====
+    pub fn session_separator(mut self, session_separator: bool) -> Self {
+        self.session_separator = session_separator;
+        self
+    }
+
+    /// Open the log file when the first log message is written to it instead of when the logger
+    /// is built, so that no empty log file is left if nothing is logged.
+    /// [`try_build`](Self::try_build) still checks that its directory exists and is writable,
+    /// log messages which can not be written later are dropped.
+    /// Default is false.
+    pub fn lazy_file(mut self, lazy_file: bool) -> Self {
+        self.lazy_file = lazy_file;
+        self
+    }
+
+    /// Take an exclusive advisory lock (`flock`) on the log file when the logger is built
+    /// (Unix only), so that another process using the same log file is detected.
+    /// [`JloggerBuilder::try_build`] fails with [`JloggerError::LogFileLocked`] if the lock is
+    /// held by another process. The log file is opened when the logger is built even with
+    /// [`lazy_file`](Self::lazy_file).
+    /// Default is false.
+    #[cfg(unix)]
+    pub fn exclusive_lock(mut self, exclusive_lock: bool) -> Self {
+        self.exclusive_lock = exclusive_lock;
+        self
+    }
+
+    /// Flush written log messages to the storage device with [`File::sync_data`] at most once
+    /// per `interval`, so that they survive a crash of the system. A zero interval syncs after
+    /// every log message.
+    /// The sync is done by the logging thread after a write, log messages written after the last
+    /// sync are only synced with the next log message.
+    ///
+    /// Syncing is expensive (milliseconds on flash storage), a short interval slows down logging
+    /// considerably.
+    pub fn fsync_interval(mut self, interval: Duration) -> Self {
+        self.fsync_interval = Some(interval);
+        self
+    }
+
+    /// What to do when writing to the log file fails because the device is full (ENOSPC).
+    /// Default is [`DiskFullPolicy::Drop`].
+    pub fn on_disk_full(mut self, policy: DiskFullPolicy) -> Self {
+        self.disk_full_policy = policy;
+        self
+    }
+
+    /// Retry a failed write to the log file up to `attempts` times, e.g. for transient errors
+    /// on a network file system. The first retry waits `backoff`, which doubles with each
+    /// further retry. If the write still fails, it is handled as without retries (see
+    /// [`on_disk_full`](Self::on_disk_full)). An interrupted write (EINTR) is always retried
+    /// immediately.
+    /// Default is no retry.
+    pub fn write_retry(mut self, attempts: u32, backoff: Duration) -> Self {
+        self.write_retry = (attempts, backoff);
+        self
+    }
+
+    /// Whether a log message waits for the log file while another thread writes to it (e.g.
+    /// syncing it, see [`fsync_interval`](Self::fsync_interval)).
+    /// With [`LockMode::TrySkip`] the log message is not written to the log file and counted as
+    /// dropped instead, so that latency-sensitive threads never block on it.
+    /// Default is [`LockMode::Block`].
+    pub fn file_lock_mode(mut self, mode: LockMode) -> Self {
+        self.file_lock_mode = mode;
+        self
+    }
+
+    /// Number of log messages kept while the log file is paused by
+    /// [`JloggerGuard::pause_file`], they are written when it is resumed. Further log messages
+    /// are dropped.
+    /// Default is 0, all log messages are dropped while paused.
+    pub fn pause_buffer(mut self, pause_buffer: usize) -> Self {
+        self.pause_buffer = pause_buffer;
+        self
+    }
+
+    /// Reopen the log file when the process receives `signum` (Unix only), e.g. `SIGHUP` after
+    /// the log file was moved away by logrotate. The log file is reopened in append mode before
+    /// the next log message is written, and so are the log files of the [`route`](Self::route)s.
+    ///
+    /// The signal handler is installed when the logger is built, [`try_build`](Self::try_build)
+    /// fails with [`JloggerError::InvalidSignal`] if `signum` is not a valid signal or can not
+    /// be caught.
+    #[cfg(unix)]
+    pub fn reopen_signal(mut self, signum: std::os::raw::c_int) -> Self {
+        self.reopen_signal = Some(signum);
+        self
+    }
+
+    /// Append the hour to the name of the log file, e.g. `/tmp/my_log.log-20240115-13`, and
+    /// switch to a new log file when the hour of a log message changes, as given by
+    /// [`clock`](Self::clock).
+    /// Default is false.
+    ///
+    /// The hourly log files are kept forever unless
+    /// [`max_hourly_files`](Self::max_hourly_files) or [`max_hourly_age`](Self::max_hourly_age)
+    /// is set.
+    pub fn rotate_hourly(mut self, rotate_hourly: bool) -> Self {
+        self.rotate_hourly = rotate_hourly;
+        self
+    }
+
+    /// Keep at most `max` hourly log files, including the current one, see
+    /// [`rotate_hourly`](Self::rotate_hourly). The oldest ones are removed when the logger is
+    /// built and whenever a new hourly log file is opened.
+    /// Default is to keep all of them.
+    pub fn max_hourly_files(mut self, max: usize) -> Self {
+        self.max_hourly_files = Some(max);
+        self
+    }
+
+    /// Remove the hourly log files last modified more than `max_age` ago, except the newest one,
+    /// see [`rotate_hourly`](Self::rotate_hourly). They are removed when the logger is built and
+    /// whenever a new hourly log file is opened.
+    /// Default is to keep all of them.
+    pub fn max_hourly_age(mut self, max_age: Duration) -> Self {
+        self.max_hourly_age = Some(max_age);
+        self
+    }
+
+    /// Add runtime information to log message.
+    /// If the current thread name is set, it will be used as runtime information, otherwise
+    /// process name is used
+    ///
+    /// >DEBUG thread1 : logging from thread thread1.  
+    /// >DEBUG jlogger-cac0970c6f073082 : logging from a thread whose name is not set.
+    ///
+    ///
+    pub fn log_runtime(mut self, log_runtime: bool) -> Self {
+        self.log_runtime = log_runtime;
+        self
+    }
+
+    /// Add a numeric thread id to the runtime information, e.g. to tell apart threads with the
+    /// same name. Ids start at 1 and are assigned in the order threads log for the first time.
+    /// Implies [`JloggerBuilder::log_runtime`].
+    ///
+    /// >DEBUG thread1#2 : logging from thread thread1.
+    pub fn log_thread_id(mut self, log_thread_id: bool) -> Self {
+        self.log_thread_id = log_thread_id;
+        self
+    }
+
+    /// Add a sequence number to each log message, starting at 1 and incremented for every
+    /// outputted line, so that gaps reveal lost log messages.
+    /// Default is false.
+    ///
+    /// >#3 INFO  : this is info
+    pub fn log_seq(mut self, log_seq: bool) -> Self {
+        self.log_seq = log_seq;
+        self
+    }
+
+    /// Time stamp string format, only take effect when time stamp is enable in the log.
+    /// * TimeStamp  
+    ///   Timestamp (from system boot) will be outputted in the log message, see
+    ///   [`timestamp_base`](Self::timestamp_base).
+    ///   > 9080.163365118 DEBUG test_debug_macro : src/lib.rs-364 : this is debug  
+    ///   > 9083.164066687 INFO  test_debug_macro : this is info
+    /// * TimeLocal  
+    ///   Date and time are printed in the log message.  
+    ///   > 2022-05-17 13:00:03 DEBUG : src/lib.rs-363 : this is debug  
+    ///   > 2022-05-17 13:00:06 INFO  : this is info
+    /// * TimeNone  
+    ///   No timestamp included in the log message.
+    /// * Relative  
+    ///   Time elapsed since the process started, easy to read when watching live output.
+    ///   The process start time is only known on Linux, the time elapsed since the logger was
+    ///   built is outputted on the other systems.
+    ///   > +0.003s DEBUG : src/lib.rs-363 : this is debug  
+    ///   > +1.204s INFO  : this is info
+    /// * EpochNanos  
+    ///   Nanoseconds since the Unix epoch as a plain integer, easy to parse by programs.
+    ///   Milliseconds are outputted instead for times too late for nanoseconds (after 2262).
+    ///   > 1652760003164066687 INFO  : this is info
+    /// * LocalWithUptime  
+    ///   Date and time followed by the seconds since the system boot, to correlate with the
+    ///   kernel log while keeping the time readable. The uptime does not depend on
+    ///   [`timestamp_base`](Self::timestamp_base).
+    ///   > 2024-01-15 13:00:03 [9080.163365118] INFO  : this is info
+    pub fn log_time(mut self, time_format: LogTimeFormat) -> Self {
+        self.time_format = time_format;
+        self
+    }
+
+    /// What the seconds of [`LogTimeFormat::TimeStamp`] are counted from.
+    /// * Boot  
+    ///   Seconds since the system boot, the same as the kernel log.
+    ///   > 9080.163365118 INFO  : this is info
+    /// * Epoch  
+    ///   Seconds since the Unix epoch (1970-01-01 00:00:00 UTC).
+    ///   > 1652760006.163365118 INFO  : this is info
+    ///
+    /// Default is Boot.
+    pub fn timestamp_base(mut self, base: TimeStampBase) -> Self {
+        self.timestamp_base = base;
+        self
+    }
+
+    /// Append the UTC offset to the date and time of [`LogTimeFormat::TimeLocal`].
+    /// Default is false.
+    ///
+    /// >2024-01-15 13:00:03 +09:00 INFO  : this is info
+    pub fn log_utc_offset(mut self, log_utc_offset: bool) -> Self {
+        self.log_utc_offset = log_utc_offset;
+        self
+    }
+
+    /// Time stamp string format used for the console only, overriding
+    /// [`log_time`](Self::log_time).
+    pub fn console_time(mut self, time_format: LogTimeFormat) -> Self {
+        self.console_time_format = Some(time_format);
+        self
+    }
+
+    /// Time stamp string format used for the log file only, overriding
+    /// [`log_time`](Self::log_time).
+    ///
+    /// ```
+    ///     use jlogger::{JloggerBuilder, LogTimeFormat};
+    ///
+    ///     // No timestamp on the console, but keep it in the log file.
+    ///     JloggerBuilder::new()
+    ///        .log_file(Some("/tmp/my_log.log"), false)
+    ///        .console_time(LogTimeFormat::TimeNone)
+    ///        .file_time(LogTimeFormat::TimeLocal);
+    /// ```
+    pub fn file_time(mut self, time_format: LogTimeFormat) -> Self {
+        self.file_time_format = Some(time_format);
+        self
+    }
+
+    /// Clock providing the time outputted in log messages.
+    /// Default is [`SystemClock`], a fixed clock can be used to get deterministic output in tests.
+    pub fn clock(mut self, clock: impl Clock + 'static) -> Self {
+        self.clock = Box::new(clock);
+        self
+    }
+
+    /// How the level is displayed in the log message.
+    /// * Full  
+    ///   > INFO  : this is info
+    /// * Short  
+    ///   > I : this is info
+    /// * Symbol  
+    ///   > ℹ : this is info
+    ///
+    /// Default is Full.
+    pub fn level_style(mut self, level_style: LevelStyle) -> Self {
+        self.level_style = level_style;
+        self
+    }
+
+    /// Display the given levels with custom names instead of the
+    /// [`level_style`](Self::level_style) token. Filtering is not affected.
+    ///
+    /// ```
+    ///     use jlogger::JloggerBuilder;
+    ///     use log::Level;
+    ///
+    ///     // CRIT  : disk failure
+    ///     JloggerBuilder::new().level_names(&[(Level::Error, "CRIT "), (Level::Info, "NOTICE")]);
+    /// ```
+    pub fn level_names(mut self, names: &[(log::Level, &str)]) -> Self {
+        for (level, name) in names {
+            self.level_names.retain(|(l, _)| l != level);
+            self.level_names.push((*level, name.to_string()));
+        }
+        self
+    }
+
+    /// Order of the fields in the log message.
+    /// Default is `[Seq, Timestamp, Level, Runtime, Version, Severity, Message]`, fields not listed are not outputted.
+    ///
+    /// ```
+    ///     use jlogger::{JloggerBuilder, LogField, LogTimeFormat};
+    ///
+    ///     // INFO  2022-05-17 13:00:06 : this is info
+    ///     JloggerBuilder::new()
+    ///        .log_time(LogTimeFormat::TimeLocal)
+    ///        .field_order(&[LogField::Level, LogField::Timestamp, LogField::Message]);
+    /// ```
+    ///
+    /// # Panics
+    /// Panics if `field_order` does not contain `LogField::Message`.
+    pub fn field_order(mut self, field_order: &[LogField]) -> Self {
+        assert!(
+            field_order.contains(&LogField::Message),
+            "field_order must contain LogField::Message"
+        );
+        self.field_order = field_order.to_vec();
+        self
+    }
+
+    /// Separator put after each field preceding the message, e.g. `"\t"` to make the timestamp,
+    /// level and runtime fields tab separated.
+    /// Default is a single space.
+    ///
+    /// >2022-05-17 13:00:06\tINFO \t: this is info
+    pub fn field_separator(mut self, separator: &str) -> Self {
+        self.field_separator = separator.to_string();
+        self
+    }
+
+    /// Format console log messages with `template` instead of
+    /// [`field_order`](Self::field_order). The following placeholders are replaced, `{{` and
+    /// `}}` are a literal `{` and `}`:
+    ///
+    /// | Placeholder | Replaced with                                          |
+    /// |-------------|--------------------------------------------------------|
+    /// | `{time}`    | Time stamp in the console time format                  |
+    /// | `{level}`   | Level, see [`level_style`](Self::level_style)          |
+    /// | `{target}`  | Target                                                 |
+    /// | `{file}`    | Source file                                            |
+    /// | `{line}`    | Source line                                            |
+    /// | `{runtime}` | Thread or process name                                 |
+    /// | `{seq}`     | Sequence number, see [`log_seq`](Self::log_seq)        |
+    /// | `{msg}`     | Log message without the location prefix of the macros  |
+    ///
+    /// [`try_build`](Self::try_build) fails with [`JloggerError::InvalidTemplate`] if the
+    /// template has an unknown placeholder.
+    ///
+    /// ```
+    ///     use jlogger::JloggerBuilder;
+    ///
+    ///     // INFO  this is info
+    ///     JloggerBuilder::new().console_template("{level} {msg}");
+    /// ```
+    pub fn console_template(mut self, template: &str) -> Self {
+        self.console_template = Some(template.to_string());
+        self
+    }
+
+    /// Format log file messages with `template` instead of [`field_order`](Self::field_order),
+    /// see [`console_template`](Self::console_template) for the placeholders.
+    ///
+    /// ```
+    ///     use jlogger::JloggerBuilder;
+    ///
+    ///     // 2022-05-17 13:00:06 INFO  myapp src/main.rs:12 this is info
+    ///     JloggerBuilder::new().file_template("{time} {level} {target} {file}:{line} {msg}");
+    /// ```
+    pub fn file_template(mut self, template: &str) -> Self {
+        self.file_template = Some(template.to_string());
+        self
+    }
+
+    /// Write console log messages to `writer` instead of stderr, e.g. to capture them in tests.
+    /// Only used when [`log_console`](Self::log_console) is enabled.
+    pub fn console_writer(mut self, writer: impl Write + Send + 'static) -> Self {
+        self.console = Some(Box::new(writer));
+        self
+    }
+
+    /// Additional console device (e.g. `/dev/tty1`) log messages are written to, together with
+    /// stderr if [`log_console`](Self::log_console) is enabled. Can be called multiple times.
+    /// The device is opened when the logger is built, a device which can not be opened is skipped.
+    pub fn console_device(mut self, device: &str) -> Self {
+        self.console_devices.push(device.to_string());
+        self
+    }
+
+    /// Wrap console log messages longer than the terminal width, continuation lines are
+    /// indented. Colors do not count toward the width. The log file and the
+    /// [`console_device`](Self::console_device)s are not affected.
+    /// Nothing is wrapped if stderr is not a terminal or a
+    /// [`console_writer`](Self::console_writer) is set.
+    /// Default is false.
+    ///
+    /// >INFO  : a long log message which does not fit  
+    /// >    into the terminal
+    pub fn console_wrap(mut self, console_wrap: bool) -> Self {
+        self.console_wrap = console_wrap;
+        self
+    }
+
+    /// Show the target on the console in a color derived from its name, so that the log
+    /// messages of each module are easy to tell apart. The same target always gets the same
+    /// color. The target is only shown if [`LogField::Target`] is in the
+    /// [`field_order`](Self::field_order) or in the [`console_template`](Self::console_template).
+    /// Other outputs are not affected.
+    /// Default is false.
+    pub fn color_targets(mut self, color_targets: bool) -> Self {
+        self.color_targets = color_targets;
+        self
+    }
+
+    /// Output at most `per_sec` log messages per second to the console (stderr and console
+    /// devices), the log file is not affected.
+    /// When log messages start being dropped, a `(console throttled)` notice is outputted.
+    pub fn console_rate_limit(mut self, per_sec: u32) -> Self {
+        self.console_rate_limit = Some(per_sec);
+        self
+    }
+
+    /// Collect console log messages and write them at once when `interval` has passed since the
+    /// first collected one, to reduce the contention on stderr when many threads log.
+    /// An ERROR log message and [`log::logger().flush()`](log::Log::flush) write the collected
+    /// log messages immediately.
+    ///
+    /// Log messages are delayed on the console by up to `interval`, and only written when the
+    /// next log message arrives after it. Call `log::logger().flush()` before exiting, otherwise
+    /// the last log messages may never be printed.
+    pub fn console_batch(mut self, interval: Duration) -> Self {
+        self.console_batch = Some(interval);
+        self
+    }
+
+    /// Write log messages to the kernel log (`/dev/kmsg`, Linux only) with the syslog priority
+    /// derived from the level, which is useful for early-boot programs.
+    /// If `/dev/kmsg` can not be opened, the kernel log is skipped.
+    /// Default is false.
+    pub fn log_kmsg(mut self, log_kmsg: bool) -> Self {
+        self.log_kmsg = log_kmsg;
+        self
+    }
+
+    /// Keep the last `capacity` log messages in memory, e.g. for a diagnostics endpoint, see
+    /// [`JloggerGuard::snapshot`]. The log messages are formatted like in the log file.
+    /// Taking a snapshot does not block logging for longer than copying a few log messages.
+    /// A snapshot has no gaps: a log message which is still being added when it is taken is left
+    /// out together with the following ones. A `capacity` of 0 disables it.
+    pub fn memory_ring(mut self, capacity: usize) -> Self {
+        self.memory_ring = (capacity > 0).then_some(capacity);
+        self
+    }
+
+    /// Send log messages, one line each, to the Unix domain socket at `path` (Unix only), e.g.
+    /// a local log collector.
+    /// The socket is connected when the logger is built and reconnected when the connection is
+    /// lost, log messages which can not be sent are dropped.
+    #[cfg(unix)]
+    pub fn log_unix_socket(mut self, path: &str) -> Self {
+        self.unix_socket = Some(path.to_string());
+        self
+    }
+
+    /// Write log messages to the Android logcat with `tag` and the Android log priority derived
+    /// from the level (Android only, requires the `android` feature).
+    #[cfg(all(feature = "android", target_os = "android"))]
+    pub fn log_logcat(mut self, tag: &str) -> Self {
+        self.logcat_tag = Some(tag.to_string());
+        self
+    }
+
+    /// Report log messages to the Windows Event Log as event source `source`, with the event
+    /// type derived from the level (Windows only, requires the `windows` feature).
+    /// If the event source can not be registered, the event log is skipped.
+    #[cfg(all(feature = "windows", windows))]
+    pub fn log_eventlog(mut self, source: &str) -> Self {
+        self.eventlog_source = Some(source.to_string());
+        self
+    }
+
+    /// Send every log message as a structured [`LogRecord`] to `sender`, so that the
+    /// application can route or store log messages itself.
+    ///
+    /// ```
+    ///     use jlogger::JloggerBuilder;
+    ///     use std::sync::mpsc;
+    ///
+    ///     let (sender, receiver) = mpsc::channel();
+    ///     let _guard = JloggerBuilder::new().record_channel(sender).build();
+    ///
+    ///     std::thread::spawn(move || {
+    ///         for record in receiver {
+    ///             println!("{} {}", record.level, record.message);
+    ///         }
+    ///     });
+    /// ```
+    pub fn record_channel(mut self, sender: Sender<LogRecord>) -> Self {
+        self.record_channel = Some(sender);
+        self
+    }
+
+    /// Replace every occurrence of `secret` in log messages with `***` before they are outputted
+    /// to any sink. Can be called multiple times.
+    ///
+    /// ```
+    ///     use jlogger::JloggerBuilder;
+    ///
+    ///     let token = std::env::var("API_TOKEN").unwrap_or_default();
+    ///     let _guard = JloggerBuilder::new().redact(&token).build();
+    /// ```
+    pub fn redact(mut self, secret: &str) -> Self {
+        // An empty pattern would match everywhere.
+        if !secret.is_empty() {
+            self.redactions.push(secret.to_string());
+        }
+        self
+    }
+
+    /// Shorten the file path of the location added by the jlogger macros to its last `n`
+    /// components, e.g. `sub/file.rs` for `src/module/sub/file.rs` with n=2.
+    /// Default is the full path.
+    ///
+    /// >INFO  : file.rs-12 : this is info
+    pub fn path_components(mut self, n: usize) -> Self {
+        self.path_components = Some(n);
+        self
+    }
+
+    /// Replace log messages with their location (`file-line`), keeping the other fields, e.g.
+    /// to audit which code paths run at the lowest cost. The arguments of the log messages are
+    /// not evaluated. Log messages without a location are outputted as `?`.
+    /// Default is false.
+    ///
+    /// >2022-05-17 13:00:03 INFO  : src/main.rs-12
+    pub fn metadata_only(mut self, metadata_only: bool) -> Self {
+        self.metadata_only = metadata_only;
+        self
+    }
+
+    /// Remove the line breaks at the end of log messages, so that every log message ends with
+    /// exactly one line break instead of being followed by empty lines.
+    /// Default is false.
+    pub fn normalize_newlines(mut self, normalize_newlines: bool) -> Self {
+        self.normalize_newlines = normalize_newlines;
+        self
+    }
+
+    /// Split a log message longer than `max_len` bytes into several lines instead of writing one
+    /// very long line, for consumers limiting the line length.
+    /// Every line carries the same correlation id and all but the last one are marked with
+    /// `(cont.)`.
+    ///
+    /// >INFO  : aaaaaaaaaa (cont.) [split:1]  
+    /// >INFO  : aaaaa [split:1]
+    pub fn max_line_len_split(mut self, max_len: usize) -> Self {
+        self.max_line_len_split = Some(max_len);
+        self
+    }
+
+    /// Output each line of a multi-line log message with the full prefix (time stamp, level,
+    /// file and line, ...), so that every line can be attributed when searching the log.
+    /// Default is false.
+    ///
+    /// >2022-05-17 13:00:03 DEBUG : src/main.rs-12 : first line  
+    /// >2022-05-17 13:00:03 DEBUG : src/main.rs-12 : second line
+    pub fn prefix_multiline(mut self, prefix_multiline: bool) -> Self {
+        self.prefix_multiline = prefix_multiline;
+        self
+    }
+
+    /// Append a backtrace of the call site to ERROR log messages.
+    /// The backtrace is only captured if it is enabled by the `RUST_BACKTRACE` or
+    /// `RUST_LIB_BACKTRACE` environment variable, see [`std::backtrace::Backtrace::capture`].
+    /// Capturing a backtrace is expensive. Default is false.
+    ///
+    /// >ERROR : src/main.rs-12 : failed to open config  
+    /// >stack backtrace:  
+    /// >   0: myapp::load_config  
+    /// >             at ./src/main.rs:12:5
+    pub fn backtrace_on_error(mut self, backtrace_on_error: bool) -> Self {
+        self.backtrace_on_error = backtrace_on_error;
+        self
+    }
+
+    /// Suppress a log message if the same message was logged within `window`, no matter from
+    /// which call site.
+    /// Messages are compared by the hash of their text, excluding the location prefix added by
+    /// the jlogger macros. When the window of a message with suppressed duplicates closes, a
+    /// summary with their number is outputted, by a background thread checking the windows
+    /// every `window` or with the next log message. The pending summaries are outputted when the
+    /// logger is flushed (e.g. when the [`JloggerGuard`] is dropped).
+    ///
+    /// >INFO  : (suppressed 12 duplicates of "connection refused")
+    pub fn dedup_by_hash(mut self, window: Duration) -> Self {
+        self.dedup_window = Some(window);
+        self
+    }
+
+    /// Log the first occurrence of a message immediately and suppress its duplicates within
+    /// `window`, no matter from which call site.
+    /// When the window closes, a summary with the number of suppressed duplicates and the last
+    /// one is outputted. The windows are checked every `window` by a background thread and when
+    /// the next log message is logged, all the pending summaries are outputted by
+    /// [`log::Log::flush`].
+    ///
+    /// >ERROR : src/db.rs-42 : connection refused  
+    /// >ERROR : (suppressed 12 duplicates of "connection refused", last: src/db.rs-57 : connection refused)
+    pub fn burst_summary(mut self, window: Duration) -> Self {
+        self.burst_window = Some(window);
+        self
+    }
+
+    /// Log the statistics of the logger at INFO level every `interval` from a background
+    /// thread: the number of log messages per level (see [`JloggerGuard::level_counts`]), the
+    /// number of dropped log messages and the bytes written to the log file.
+    ///
+    /// >INFO  : jlogger stats: error=0 warn=2 info=120 debug=0 trace=0 dropped=0 bytes=6012
+    ///
+    /// The thread stops when the [`JloggerGuard`] is dropped.
+    pub fn stats_interval(mut self, interval: Duration) -> Self {
+        self.stats_interval = Some(interval);
+        self
+    }
+
+    /// Log a `heartbeat` at INFO level from a background thread when nothing was logged for
+    /// `interval`, so that a monitor can tell that the program is still alive.
+    ///
+    /// >INFO  : heartbeat
+    ///
+    /// The thread stops when the [`JloggerGuard`] is dropped.
+    pub fn heartbeat(mut self, interval: Duration) -> Self {
+        self.heartbeat = Some(interval);
+        self
+    }
+
+    /// Message logged by the macros called without arguments such as `jdebug!()`, it may be
+    /// empty to only log the file name and line number.
+    /// Default is "arrived.".
+    ///
+    /// >DEBUG : src/main.rs-4 : here
+    pub fn empty_message(mut self, message: &str) -> Self {
+        self.empty_message = Some(message.to_string());
+        self
+    }
+
+    /// Emit a one-line banner at INFO level describing the effective configuration right after
+    /// the logger is installed.
+    /// Default is false.
+    ///
+    /// >INFO  : jlogger started: level=DEBUG console=true file=/tmp/my_log.log console_time=TimeLocal file_time=TimeLocal runtime=true
+    pub fn log_banner(mut self, log_banner: bool) -> Self {
+        self.log_banner = log_banner;
+        self
+    }
+
+    /// Version of the program included in the banner, see [`log_banner`](Self::log_banner),
+    /// typically `env!("CARGO_PKG_VERSION")`.
+    ///
+    /// >INFO  : jlogger started: version=1.2.3 level=INFO console=true ...
+    pub fn version(mut self, version: &str) -> Self {
+        self.version = Some(version.to_string());
+        self
+    }
+
+    /// Include the working directory of the process when the logger is built in the banner,
+    /// see [`log_banner`](Self::log_banner), so that relative paths in the log messages can be
+    /// resolved.
+    /// Default is false.
+    ///
+    /// >INFO  : jlogger started: level=INFO console=true ... cwd=/home/user/myapp
+    pub fn log_cwd(mut self, log_cwd: bool) -> Self {
+        self.log_cwd = log_cwd;
+        self
+    }
+
+    /// Write the process id to `path` when the logger is built, e.g. for a daemon.
+    /// The file is removed when the returned [`JloggerGuard`] is dropped, so the guard must be
+    /// kept alive as long as the process runs: `let _guard = builder.build();`, not
+    /// `builder.build();` or `let _ = builder.build();` which remove it right away.
+    pub fn pid_file(mut self, path: &str) -> Self {
+        self.pid_file = Some(path.to_string());
+        self
+    }
+
+    /// Fail to build the logger if the `JLOGGER_LEVEL` environment variable is set to a value
+    /// which can not be parsed, instead of silently turning logging off.
+    /// Default is false.
+    pub fn strict_env(mut self, strict_env: bool) -> Self {
+        self.strict_env = strict_env;
+        self
+    }
+
+    /// Build a Jlogger and install it as the global logger.
+    /// The returned [`JloggerGuard`] can be used to control the logger at runtime.
+    ///
+    /// # Panics
+    /// Panics if [`try_build`](Self::try_build) fails.
+    pub fn build(self) -> JloggerGuard {
+        self.try_build().unwrap()
+    }
+
+    /// Same as [`build`](Self::build), but return an error instead of panicking, e.g. if the
+    /// log file can not be opened. This is the recommended way to build the logger.
+    ///
+    /// ```
+    ///     use jlogger::JloggerBuilder;
+    ///
+    ///     std::env::set_var("JLOGGER_LEVEL", "bogus");
+    ///     assert!(JloggerBuilder::new().strict_env(true).try_build().is_err());
+    /// ```
+    pub fn try_build(self) -> Result<JloggerGuard, JloggerError> {
+        if self.strict_env {
+            if let Ok(value) = std::env::var("JLOGGER_LEVEL") {
+                if parse_level_filter(&value).is_none() {
+                    return Err(JloggerError::InvalidEnv {
+                        name: "JLOGGER_LEVEL",
+                        value,
+                    });
+                }
+            }
+        }
+
+        if INSTALLED.swap(true, Ordering::SeqCst) {
+            return Err(JloggerError::AlreadyInstalled);
+        }
+
+        let guard = self.install();
+        if guard.is_err() {
+            INSTALLED.store(false, Ordering::SeqCst);
+        }
+        guard
+    }
+
+    /// Prepare the logger and install it as the global logger, see [`try_build`](Self::try_build).
+    fn install(mut self) -> Result<JloggerGuard, JloggerError> {
+        self.prepare()?;
+
+        let log_banner = self.log_banner;
+        let empty_message = self.empty_message.take();
+        let pid_file = self.pid_file.clone();
+
+        if let Some(path) = &pid_file {
+            fs::write(path, format!("{}\n", std::process::id())).map_err(|error| {
+                JloggerError::Io {
+                    path: path.clone(),
+                    error,
+                }
+            })?;
+        }
+
+        // Only fails if a logger which is not a Jlogger was installed, the leaked logger is
+        // never used then.
+        let logger: &'static Jlogger = Box::leak(Box::new(self.into_logger()));
+        if log::set_logger(logger).is_err() {
+            if let Some(path) = &pid_file {
+                let _ = fs::remove_file(path);
+            }
+            return Err(JloggerError::AlreadyInstalled);
+        }
+        log::set_max_level(LevelFilter::Trace);
+
+        if let Some(empty_message) = empty_message {
+            *EMPTY_MESSAGE.write().unwrap() = std::borrow::Cow::Owned(empty_message);
+        }
+        if log_banner {
+            logger.log_banner();
+        }
+        logger.start_stats();
+        logger.start_heartbeat();
+        logger.start_summaries();
+
+        Ok(JloggerGuard { logger })
+    }
+
+    /// Check the settings and open the log files of this builder and of its routes, so that the
+    /// errors are reported by [`try_build`](Self::try_build).
+    fn prepare(&mut self) -> Result<(), JloggerError> {
+        for template in self.console_template.iter().chain(&self.file_template) {
+            if let Err(placeholder) = parse_template(template) {
+                return Err(JloggerError::InvalidTemplate {
+                    template: template.clone(),
+                    placeholder,
+                });
+            }
+        }
+
+        #[cfg(unix)]
+        if let Some(signum) = self.reopen_signal {
+            install_reopen_handler(signum)
+                .map_err(|error| JloggerError::InvalidSignal { signum, error })?;
+        }
+
+        self.prepare_log_file()?;
+        if let (true, Some(base)) = (self.rotate_hourly, &self.log_file_name) {
+            prune_hourly_log_files(base, self.max_hourly_files, self.max_hourly_age);
+        }
+
+        for (_, builder) in &mut self.routes {
+            builder.prepare()?;
+        }
+
+        Ok(())
+    }
+
+    /// Open the log file, so that failing to open it is reported by
+    /// [`try_build`](Self::try_build). It is also locked if
+    /// [`exclusive_lock`](Self::exclusive_lock) is enabled, otherwise it is left to be opened
+    /// later with [`lazy_file`](Self::lazy_file).
+    fn prepare_log_file(&mut self) -> Result<(), JloggerError> {
+        let path = match &self.log_file_name {
+            Some(name) => self.log_file_opened_path(name),
+            None => return Ok(()),
+        };
+
+        #[cfg(unix)]
+        if self.exclusive_lock {
+            let file = open_locked_log_file(&path, self.log_file_append).map_err(|error| {
+                if error.kind() == io::ErrorKind::WouldBlock {
+                    JloggerError::LogFileLocked { path: path.clone() }
+                } else {
+                    JloggerError::Io {
+                        path: path.clone(),
+                        error,
+                    }
+                }
+            })?;
+            self.opened_file = Some((path, file));
+            return Ok(());
+        }
+
+        if self.lazy_file {
+            check_log_file_dir(&path).map_err(|error| JloggerError::Io {
+                path: path.clone(),
+                error,
+            })?;
+        } else {
+            let file =
+                open_log_file(&path, self.log_file_append).map_err(|error| JloggerError::Io {
+                    path: path.clone(),
+                    error,
+                })?;
+            self.opened_file = Some((path, file));
+        }
+
+        Ok(())
+    }
+
+    /// Path of the log file named `name`, with the current hour appended if
+    /// [`rotate_hourly`](Self::rotate_hourly) is set.
+    fn log_file_opened_path(&self, name: &str) -> String {
+        if self.rotate_hourly {
+            hourly_log_file(name, &self.clock.now())
+        } else {
+            name.to_string()
+        }
+    }
+
+    fn into_logger(mut self) -> Jlogger {
+        let kmsg = if self.log_kmsg {
+            fs::OpenOptions::new()
+                .write(true)
+                .open(&self.kmsg_path)
+                .map_err(|e| eprintln!("jlogger: failed to open {}: {}", self.kmsg_path, e))
+                .ok()
+                .map(RwLock::new)
+        } else {
+            None
+        };
+
+        // The terminal width is the one of stderr, a custom console writer is not wrapped.
+        let console_width = if self.console_wrap {
+            self.console_width
+                .or_else(|| self.console.is_none().then(terminal_width).flatten())
+                .filter(|w| *w > WRAP_INDENT + 1)
+        } else {
+            None
+        };
+
+        Jlogger {
+            log_console: self.log_console,
+            console: Mutex::new(self.console.take()),
+            console_closed: AtomicBool::new(false),
+            log_file: RwLock::new(self.log_file_name.take().map(|base| {
+                let (name, writer): (String, LogWriter) =
+                    if let Some((name, file)) = self.opened_file.take() {
+                        (name, Box::new(file))
+                    } else {
+                        let name = self.log_file_opened_path(&base);
+                        let writer: LogWriter = if self.lazy_file {
+                            Box::new(LazyLogFile {
+                                name: name.clone(),
+                                append: self.log_file_append,
+                                file: None,
+                            })
+                        } else {
+                            Box::new(open_log_file(&name, self.log_file_append).unwrap())
+                        };
+                        (name, writer)
+                    };
+
+                let mut log_file = LogFile::new(name, writer);
+                if self.rotate_hourly {
+                    log_file.hourly_base = Some(base);
+                }
+                if self.session_separator && self.log_file_append {
+                    log_file.header = Some(session_header(&self.clock.now()));
+                }
+                log_file
+            })),
+            session_separator: self.session_separator,
+            rotate_hourly: self.rotate_hourly,
+            max_hourly_files: self.max_hourly_files,
+            max_hourly_age: self.max_hourly_age,
+            #[cfg(unix)]
+            exclusive_lock: self.exclusive_lock,
+            fsync_interval: self.fsync_interval,
+            disk_full_policy: self.disk_full_policy,
+            file_lock_mode: self.file_lock_mode,
+            write_retry: self.write_retry,
+            dropped_records: AtomicU64::new(0),
+            bytes_written: AtomicU64::new(0),
+            stats_interval: self.stats_interval,
+            heartbeat: self.heartbeat,
+            stopped: AtomicBool::new(false),
+            last_logged: AtomicU64::new(0),
+            file_paused: Mutex::new(None),
+            pause_buffer: self.pause_buffer,
+            level_counts: Default::default(),
+            log_runtime: self.log_runtime,
+            log_thread_id: self.log_thread_id,
+            log_seq: self.log_seq,
+            seq: AtomicU64::new(0),
+            console_time_format: AtomicU8::new(
+                self.console_time_format.unwrap_or(self.time_format) as u8,
+            ),
+            file_time_format: AtomicU8::new(self.file_time_format.unwrap_or(self.time_format) as u8),
+            level_style: self.level_style,
+            level_names: self.level_names,
+            field_order: self.field_order,
+            field_separator: self.field_separator,
+            console_template: self
+                .console_template
+                .map(|t| parse_template(&t).expect("template checked by try_build")),
+            file_template: self
+                .file_template
+                .map(|t| parse_template(&t).expect("template checked by try_build")),
+            dedup: self
+                .dedup_window
+                .map(|window| Mutex::new(DedupCache::new(window))),
+            burst: self
+                .burst_window
+                .map(|window| Mutex::new(BurstCache::new(window))),
+            color_targets: self.color_targets,
+            console_width,
+            console_devices: self
+                .console_devices
+                .iter()
+                .filter_map(|device| {
+                    fs::OpenOptions::new()
+                        .append(true)
+                        .open(device)
+                        .map_err(|e| {
+                            eprintln!("jlogger: failed to open console device {}: {}", device, e)
+                        })
+                        .ok()
+                        .map(RwLock::new)
+                })
+                .collect(),
+            console_rate_limit: self
+                .console_rate_limit
+                .map(|rate| Mutex::new(TokenBucket::new(rate))),
+            console_batch: self.console_batch.map(|interval| {
+                Mutex::new(ConsoleBatch {
+                    interval,
+                    pending: String::new(),
+                    since: Instant::now(),
+                })
+            }),
+            kmsg,
+            memory_ring: self.memory_ring.map(MemoryRing::new),
+            #[cfg(unix)]
+            unix_socket: self.unix_socket.map(|path| {
+                // Not being able to connect yet is fine, it is retried for every log message.
+                let stream = std::os::unix::net::UnixStream::connect(&path).ok();
+                Mutex::new(UnixSocketSink { path, stream })
+            }),
+            #[cfg(all(feature = "android", target_os = "android"))]
+            logcat_tag: self
+                .logcat_tag
+                .map(|t| std::ffi::CString::new(t.replace('\0', "")).unwrap()),
+            #[cfg(all(feature = "windows", windows))]
+            eventlog: self.eventlog_source.and_then(|source| {
+                let handle =
+                    unsafe { RegisterEventSourceW(std::ptr::null(), to_wide(&source).as_ptr()) };
+                if handle.is_null() {
+                    eprintln!(
+                        "jlogger: failed to register event source {}: {}",
+                        source,
+                        io::Error::last_os_error()
+                    );
+                    None
+                } else {
+                    Some(EventLog(handle))
+                }
+            }),
+            record_channel: self.record_channel,
+            redactions: self.redactions,
+            normalize_newlines: self.normalize_newlines,
+            path_components: self.path_components,
+            metadata_only: self.metadata_only,
+            muted_targets: self.muted_targets,
+            filters: self.filters,
+            target_levels: self.target_levels,
+            routes: self
+                .routes
+                .into_iter()
+                .map(|(target, builder)| (target, builder.into_logger()))
+                .collect(),
+            target_versions: self.target_versions,
+            severity_map: self.severity_map,
+            max_line_len_split: self.max_line_len_split,
+            split_id: AtomicU64::new(0),
+            prefix_multiline: self.prefix_multiline,
+            backtrace_on_error: self.backtrace_on_error,
+            start_time: self.clock.now(),
+            clock: self.clock,
+            timestamp_base: self.timestamp_base,
+            boot_start: std::sync::OnceLock::new(),
+            boot_time: self.boot_time,
+            process_start: std::sync::OnceLock::new(),
+            process_age: self.process_age,
+            log_utc_offset: self.log_utc_offset,
+            pid_file: self.pid_file,
+            version: self.version,
+            cwd: if self.log_cwd {
+                std::env::current_dir()
+                    .ok()
+                    .map(|d| d.display().to_string())
+            } else {
+                None
+            },
+            max_level: self.max_level,
+        }
+    }
+}
+
+/// Level conventionally used to log an HTTP status `code`: ERROR for 5xx, WARN for 4xx and INFO
+/// otherwise, see [`jstatus!`].
+pub fn level_for_status(code: u16) -> LevelFilter {
+    match code {
+        500..=599 => LevelFilter::Error,
+        400..=499 => LevelFilter::Warn,
+        _ => LevelFilter::Info,
+    }
+}
+
+/// Parse a level filter name, case-insensitively.
+/// Besides the level names (`off`, `error`, `warn`, `info`, `debug`, `trace`), the following
+/// aliases used by other tools are accepted:
+///
+/// | Alias     | Level |
+/// |-----------|-------|
+/// | `none`    | OFF   |
+/// | `err`     | ERROR |
+/// | `warning` | WARN  |
+/// | `verbose` | TRACE |
+///
+/// This is also how the `JLOGGER_LEVEL` environment variable is parsed.
+pub fn parse_level_filter(level: &str) -> Option<LevelFilter> {
+    match level.to_ascii_lowercase().as_str() {
+        "off" | "none" => Some(LevelFilter::Off),
+        "error" | "err" => Some(LevelFilter::Error),
+        "warn" | "warning" => Some(LevelFilter::Warn),
+        "info" => Some(LevelFilter::Info),
+        "debug" => Some(LevelFilter::Debug),
+        "trace" | "verbose" => Some(LevelFilter::Trace),
+        _ => None,
+    }
+}
+
+static NEXT_THREAD_ID: AtomicU64 = AtomicU64::new(1);
+
+/// Maximum number of log messages held back by a [`ConditionalBufferScope`], older ones are
+/// dropped.
+const CONDITIONAL_BUFFER_SIZE: usize = 1024;
+
+/// Log messages held back by a [`ConditionalBufferScope`].
+struct ConditionalBuffer {
+    /// Log messages with their full message, including the location prefix.
+    records: VecDeque<(LogRecord, String)>,
+    /// An ERROR was logged in the scope, log messages are no longer held back.
+    tripped: bool,
+}
+
+thread_local! {
+    static CORRELATION_ID: RefCell<Option<String>> = const { RefCell::new(None) };
+    static CONDITIONAL_BUFFER: RefCell<Option<ConditionalBuffer>> = const { RefCell::new(None) };
+    /// Time of a log message outputted later than it was logged, instead of the current time.
+    static RECORD_TIME: Cell<Option<chrono::DateTime<chrono::Local>>> = const { Cell::new(None) };
+    static THREAD_ID: Cell<u64> = const { Cell::new(0) };
+}
+
+/// Set the correlation id (e.g. a request id) of the current thread.
+/// Until [`clear_correlation_id`] is called, it is included in every log message logged from the
+/// current thread.
+///
+/// >INFO  [req-42] : handling request
+pub fn set_correlation_id(id: &str) {
+    CORRELATION_ID.with(|c| *c.borrow_mut() = Some(id.to_string()));
+}
+
+/// Clear the correlation id of the current thread set by [`set_correlation_id`].
+pub fn clear_correlation_id() {
+    CORRELATION_ID.with(|c| *c.borrow_mut() = None);
+}
+
+/// Hold back the DEBUG and TRACE log messages of the current thread which are filtered out by
+/// the level until an ERROR is logged, see [`conditional_buffer_scope`].
+pub struct ConditionalBufferScope {
+    /// Whether this scope started the buffering, nested scopes leave it to the outer one.
+    active: bool,
+    /// The buffer is per thread, so is the scope.
+    _not_send: std::marker::PhantomData<*const ()>,
+}
+
+impl Drop for ConditionalBufferScope {
+    fn drop(&mut self) {
+        if self.active {
+            CONDITIONAL_BUFFER.with(|b| *b.borrow_mut() = None);
+        }
+    }
+}
+
+/// Start holding back the DEBUG and TRACE log messages of the current thread which are filtered
+/// out by the level, until the returned scope is dropped.
+/// If an ERROR is logged in the scope, the held back log messages are outputted before it with
+/// their original time, and the following ones are outputted directly, so that the failure
+/// comes with its context. Otherwise they are discarded when the scope is dropped. At most the
+/// last 1024 log messages are held back.
+///
+/// ```
+///     use jlogger::{conditional_buffer_scope, jdebug, jerror};
+///
+///     fn handle(ok: bool) {
+///         let _scope = conditional_buffer_scope();
+///         jdebug!("step 1");     // Only outputted if the ERROR below is.
+///         if !ok {
+///             jerror!("failed");
+///         }
+///     }
+/// ```
+pub fn conditional_buffer_scope() -> ConditionalBufferScope {
+    let active = CONDITIONAL_BUFFER.with(|b| {
+        let mut b = b.borrow_mut();
+        if b.is_some() {
+            return false;
+        }
+
+        *b = Some(ConditionalBuffer {
+            records: VecDeque::new(),
+            tripped: false,
+        });
+        true
+    });
+
+    ConditionalBufferScope {
+        active,
+        _not_send: std::marker::PhantomData,
+    }
+}
+
+/// Hold back `record` if a [`ConditionalBufferScope`] is active on the current thread.
+/// Returns true if it should be outputted because an ERROR was logged in the scope.
+fn capture_conditional_buffer(record: &Record, now: chrono::DateTime<chrono::Local>) -> bool {
+    CONDITIONAL_BUFFER.with(|b| match b.borrow_mut().as_mut() {
+        Some(buffer) if buffer.tripped => true,
+        Some(buffer) => {
+            if buffer.records.len() == CONDITIONAL_BUFFER_SIZE {
+                buffer.records.pop_front();
+            }
+            buffer
+                .records
+                .push_back((LogRecord::new(record, now), record.args().to_string()));
+            false
+        }
+        None => false,
+    })
+}
+
+/// Stop holding back log messages in the [`ConditionalBufferScope`] of the current thread,
+/// returning those held back so far.
+fn trip_conditional_buffer() -> Vec<(LogRecord, String)> {
+    CONDITIONAL_BUFFER.with(|b| match b.borrow_mut().as_mut() {
+        Some(buffer) => {
+            buffer.tripped = true;
+            buffer.records.drain(..).collect()
+        }
+        None => Vec::new(),
+    })
+}
+
+/// Display a [`Duration`] with an adaptive unit, e.g. `1.23s`, `450ms`, `12µs` or `80ns`.
+///
+/// ```
+///     use jlogger::{jinfo, DisplayDuration};
+///     use std::time::Instant;
+///
+///     let start = Instant::now();
+///     jinfo!("done in {}", DisplayDuration(start.elapsed()));
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DisplayDuration(pub Duration);
+
+impl std::fmt::Display for DisplayDuration {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let nanos = self.0.as_nanos();
+        let (value, unit) = if nanos >= 1_000_000_000 {
+            (nanos as f64 / 1_000_000_000.0, "s")
+        } else if nanos >= 1_000_000 {
+            (nanos as f64 / 1_000_000.0, "ms")
+        } else if nanos >= 1_000 {
+            (nanos as f64 / 1_000.0, "µs")
+        } else {
+            return write!(f, "{}ns", nanos);
+        };
+
+        let value = format!("{:.2}", value);
+        let value = value.trim_end_matches('0').trim_end_matches('.');
+        write!(f, "{}{}", value, unit)
+    }
+}
+
+/// Set by [`JloggerBuilder::empty_message`] when the logger is installed.
+static EMPTY_MESSAGE: RwLock<std::borrow::Cow<'static, str>> =
+    RwLock::new(std::borrow::Cow::Borrowed("arrived."));
+
+/// Display the message logged by the macros called without arguments, see
+/// [`JloggerBuilder::empty_message`].
+#[derive(Debug, Clone, Copy)]
+pub struct EmptyMessage;
+
+impl std::fmt::Display for EmptyMessage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&EMPTY_MESSAGE.read().unwrap())
+    }
+}
+
+/// Display an error followed by the chain of its [`source`](std::error::Error::source)s, one per
+/// line, see [`jerror_chain!`].
+///
+/// >connection failed  
+/// >  caused by: connection refused
+#[derive(Debug, Clone, Copy)]
+pub struct ErrorChain<'a>(pub &'a dyn std::error::Error);
+
+impl std::fmt::Display for ErrorChain<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)?;
+
+        let mut source = self.0.source();
+        while let Some(e) = source {
+            write!(f, "\n  caused by: {}", e)?;
+            source = e.source();
+        }
+
+        Ok(())
+    }
+}
+
+/// Format `data` as a hexdump with `width` bytes per line, each line shows the offset, the bytes
+/// in hex and, if `ascii` is true, the printable characters.
+///
+/// ```
+///     let dump = jlogger::hexdump(b"Hello, jlogger!\n", 8, true);
+///     assert_eq!(
+///         dump,
+///         "00000000  48 65 6c 6c 6f 2c 20 6a  |Hello, j|\n\
+///          00000008  6c 6f 67 67 65 72 21 0a  |logger!.|"
+///     );
+/// ```
+pub fn hexdump(data: &[u8], width: usize, ascii: bool) -> String {
+    let width = width.max(1);
+
+    data.chunks(width)
+        .enumerate()
+        .map(|(i, chunk)| {
+            let hex = chunk
+                .iter()
+                .map(|b| format!("{:02x}", b))
+                .collect::<Vec<String>>()
+                .join(" ");
+            let mut line = format!("{:08x}  {:<2$}", i * width, hex, width * 3 - 1);
+
+            if ascii {
+                let text: String = chunk
+                    .iter()
+                    .map(|&b| {
+                        if b.is_ascii_graphic() || b == b' ' {
+                            b as char
+                        } else {
+                            '.'
+                        }
+                    })
+                    .collect();
+                line.push_str(format!("  |{}|", text).as_str());
+            }
+
+            line
+        })
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+/// Initialize a console logger at INFO level with local time and runtime information, which is
+/// equivalent to:
+/// ```
+///     use jlogger::{JloggerBuilder, LogTimeFormat};
+///
+///     let _guard = JloggerBuilder::new()
+///        .max_level(log::LevelFilter::Info)
+///        .log_console(true)
+///        .log_runtime(true)
+///        .log_time(LogTimeFormat::TimeLocal)
+///        .build();
+/// ```
+/// It is safe to call it more than once, only the first call installs the logger.
+pub fn init_default() {
+    static INIT: Once = Once::new();
+
+    INIT.call_once(|| {
+        // There is no caller to keep the guard, and no pid file to remove.
+        std::mem::forget(
+            JloggerBuilder::new()
+                .max_level(LevelFilter::Info)
+                .log_console(true)
+                .log_runtime(true)
+                .log_time(LogTimeFormat::TimeLocal)
+                .build(),
+        );
+    });
+}
+
+/// Build and install a logger configured by environment variables, so that the logging can be
+/// configured without changing the program. All the variables are optional.
+///
+/// | Variable         | Values                                                              | Default               |
+/// |------------------|---------------------------------------------------------------------|-----------------------|
+/// | `JLOGGER_LEVEL`  | `off`, `error`, `warn`, `info`, `debug`, `trace`, or an alias       | `info`                |
+/// | `JLOGGER_FILE`   | Path of the log file, appended to                                   | No log file           |
+/// | `JLOGGER_FORMAT` | `default`, `dev`, `production`, `compact`                           | `default`             |
+/// | `JLOGGER_TIME`   | `stamp`, `local`, `none`, `relative`, `epoch_nanos`, `local_uptime` | Depends on the format |
+///
+/// The level aliases are those of [`parse_level_filter`]: `none`, `err`, `warning` and
+/// `verbose`.
+///
+/// `default` logs the thread name and the local time like [`init_default`], the others are the
+/// [`Preset`]s of the same name: `dev` is [`Preset::DevFriendly`], `production` is
+/// [`Preset::Production`] and `compact` is [`Preset::Compact`]. jlogger has no JSON output, so
+/// `json` is rejected like any other unknown format. The time values are the [`LogTimeFormat`]
+/// variants. Values are case-insensitive, an unknown value is an [`JloggerError::InvalidEnv`]
+/// error.
+///
+/// ```no_run
+///     // JLOGGER_LEVEL=debug JLOGGER_FILE=/var/log/myapp.log JLOGGER_TIME=stamp myapp
+///     let _guard = jlogger::init_from_env().unwrap();
+/// ```
+pub fn init_from_env() -> Result<JloggerGuard, JloggerError> {
+    JloggerBuilder::from_env()?.try_build()
+}
+
+/// Number of log messages outputted per level, see [`JloggerGuard::level_counts`].
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct LevelCounts {
+    pub error: u64,
+    pub warn: u64,
+    pub info: u64,
+    pub debug: u64,
+    pub trace: u64,
+}
+
+/// Handle to the installed logger returned by [`JloggerBuilder::build`].
+/// Dropping it flushes the logger (see [`log::Log::flush`]), so it should be kept until the
+/// end of the program.
+#[must_use = "dropping the guard flushes the logger and removes the pid file"]
+pub struct JloggerGuard {
+    logger: &'static Jlogger,
+}
+
+impl Drop for JloggerGuard {
+    fn drop(&mut self) {
+        self.logger.stopped.store(true, Ordering::Relaxed);
+        self.logger.flush();
+
+        if let Some(path) = &self.logger.pid_file {
+            let _ = fs::remove_file(path);
+        }
+    }
+}
+
+impl JloggerGuard {
+    /// Number of log messages which passed the level filter so far, per level.
+    pub fn level_counts(&self) -> LevelCounts {
+        let count = |level: log::Level| {
+            self.logger.level_counts[level as usize - 1].load(Ordering::Relaxed)
+        };
+
+        LevelCounts {
+            error: count(log::Level::Error),
+            warn: count(log::Level::Warn),
+            info: count(log::Level::Info),
+            debug: count(log::Level::Debug),
+            trace: count(log::Level::Trace),
+        }
+    }
+
+    /// Write the log messages still held by the logger, e.g. collected by
+    /// [`JloggerBuilder::console_batch`], and flush the console and the log files to the storage
+    /// device with [`File::sync_data`]. Returns when everything is written, so it can be called
+    /// before [`std::process::exit`], which does not drop the guard.
+    pub fn flush_all(&self) {
+        self.logger.flush();
+        self.logger.sync_log_files();
+    }
+
+    /// Switch the log file to `log_file`, see [`JloggerBuilder::log_file`] for `append`.
+    /// The current log file is closed and the following log messages are written to the new one.
+    /// If the new log file can not be opened, the current one is kept.
+    /// The settings of the log file given to the builder still apply:
+    /// [`rotate_hourly`](JloggerBuilder::rotate_hourly),
+    /// [`session_separator`](JloggerBuilder::session_separator) and
+    /// [`exclusive_lock`](JloggerBuilder::exclusive_lock).
+    pub fn set_log_file(&self, log_file: &str, append: bool) -> io::Result<()> {
+        let logger = self.logger;
+        let now = logger.clock.now();
+        let name = if logger.rotate_hourly {
+            hourly_log_file(log_file, &now)
+        } else {
+            log_file.to_string()
+        };
+        let writer = logger.open_new_log_file(&name, append)?;
+
+        let mut f = LogFile::new(name, Box::new(writer));
+        if logger.rotate_hourly {
+            f.hourly_base = Some(log_file.to_string());
+            logger.prune_hourly_log_files(log_file);
+        }
+        if logger.session_separator && append {
+            f.header = Some(session_header(&now));
+        }
+        *logger.log_file.write().unwrap() = Some(f);
+
+        Ok(())
+    }
+
+    /// Change the time stamp format of both the console and the log file, see
+    /// [`JloggerBuilder::log_time`]. Takes effect from the next log message.
+    pub fn set_time_format(&self, time_format: LogTimeFormat) {
+        self.logger
+            .console_time_format
+            .store(time_format as u8, Ordering::Relaxed);
+        self.logger
+            .file_time_format
+            .store(time_format as u8, Ordering::Relaxed);
+    }
+
+    /// Copy of the log messages kept in memory, oldest first, see
+    /// [`JloggerBuilder::memory_ring`]. Empty if it is not enabled.
+    pub fn snapshot(&self) -> Vec<String> {
+        self.logger
+            .memory_ring
+            .as_ref()
+            .map(|ring| ring.snapshot())
+            .unwrap_or_default()
+    }
+
+    /// Stop writing to the log file, e.g. while its device is swapped, other outputs are not
+    /// affected. Log messages are kept or dropped according to [`JloggerBuilder::pause_buffer`].
+    pub fn pause_file(&self) {
+        let _log_file = self.logger.log_file.write().unwrap();
+        let mut paused = self.logger.file_paused.lock().unwrap();
+        if paused.is_none() {
+            *paused = Some(Vec::new());
+        }
+    }
+
+    /// Resume writing to the log file paused by [`pause_file`](Self::pause_file), the kept log
+    /// messages are written first.
+    pub fn resume_file(&self) {
+        let mut log_file = self.logger.log_file.write().unwrap();
+        let pending = self.logger.file_paused.lock().unwrap().take();
+
+        if let (Some(f), Some(pending)) = (log_file.as_mut(), pending) {
+            for log_message in pending {
+                self.logger.write_log_file(f, &log_message);
+            }
+        }
+    }
+}
+
+/// Log an error message prefixed with the file name and line number.
+///
+/// ```
+///     use jlogger::jerror;
+///
+///     let code = 2;
+///     jerror!();                           // src/main.rs-4 : arrived.
+///     jerror!("failed");                   // src/main.rs-5 : failed
+///     jerror!("failed with {}", code);     // src/main.rs-6 : failed with 2
+/// ```
+///
+/// The format string is checked at compile time, a mismatch between the format string and the
+/// arguments does not compile:
+/// ```compile_fail
+///     jlogger::jerror!("{} {}", 1);
+/// ```
+#[macro_export]
+macro_rules! jerror{
+    () => {
+        log::error!(
+            "{}-{} : {}",
+            file!(),
+            line!(),
+            $crate::EmptyMessage
+        );
+    };
+    ($val:tt) => {
+        log::error!(
+            "{}-{} : {}",
+            file!(),
+            line!(),
+            $val
+        );
+    };
+    ($fmt:expr,$($val:expr),* $(,)?) => {{
+        log::error!(
+            "{}-{} : {}",
+            file!(),
+            line!(),
+            format_args!($fmt, $($val),*)
+        );
+    }};
+}
+
+#[macro_export]
+macro_rules! jwarn{
+    () => {
         log::warn!(
             "{}-{} : {}",
             file!(),
             line!(),
+            $crate::EmptyMessage
+        );
+    };
+    ($val:tt) => {
+        log::warn!(
+            "{}-{} : {}",
+            file!(),
+            line!(),
+            $val
+        );
+    };
+    ($fmt:expr,$($val:expr),* $(,)?) => {{
+        log::warn!(
+            "{}-{} : {}",
+            file!(),
+            line!(),
+            format_args!($fmt, $($val),*)
+        );
+    }};
+}
+
+#[macro_export]
+macro_rules! jinfo{
+    () => {
+        log::info!(
+            "{}-{} : {}",
+            file!(),
+            line!(),
+            $crate::EmptyMessage
+        );
+    };
+    ($val:tt) => {
+        log::info!(
+            "{}-{} : {}",
+            file!(),
+            line!(),
+            $val
+        );
+    };
+    ($fmt:expr,$($val:expr),* $(,)?) => {{
+        log::info!(
+            "{}-{} : {}",
+            file!(),
+            line!(),
+            format_args!($fmt, $($val),*)
+        );
+    }};
+}
+
+#[macro_export]
+macro_rules! jdebug {
+    () => {
+        log::debug!(
+            "{}-{} : {}",
+            file!(),
+            line!(),
+            $crate::EmptyMessage
+        );
+    };
+    ($val:tt) => {
+        log::debug!(
+            "{}-{} : {}",
+            file!(),
+            line!(),
+            $val
+        );
+    };
+    ($fmt:expr,$($val:expr),* $(,)?) => {{
+        log::debug!(
+            "{}-{} : {}",
+            file!(),
+            line!(),
+            format_args!($fmt, $($val),*)
+        );
+    }};
+}
+
+#[macro_export]
+macro_rules! jtrace {
+    () => {
+        log::trace!(
+            "{}-{} : {}",
+            file!(),
+            line!(),
+            $crate::EmptyMessage
+        );
+    };
+    ($val:tt) => {
+        log::trace!(
+            "{}-{} : {}",
+            file!(),
+            line!(),
+            $val
+        );
+    };
+    ($fmt:expr,$($val:expr),* $(,)?) => {{
+        log::trace!(
+            "{}-{} : {}",
+            file!(),
+            line!(),
+            format_args!($fmt, $($val),*)
+        );
+    }};
+}
+
+/// Same as [`jerror!`] but only logs the first time the call site is executed, which is useful
+/// for one-time warnings such as deprecation notices.
+///
+/// ```
+///     use jlogger::jwarn_once;
+///
+///     for _ in 0..10 {
+///         // Only logged once.
+///         jwarn_once!("option --foo is deprecated");
+///     }
+/// ```
+#[macro_export]
+macro_rules! jerror_once {
+    ($($arg:tt)*) => {{
+        static ONCE: std::sync::atomic::AtomicBool = std::sync::atomic::AtomicBool::new(false);
+        if !ONCE.swap(true, std::sync::atomic::Ordering::Relaxed) {
+            $crate::jerror!($($arg)*);
+        }
+    }};
+}
+
+/// Same as [`jwarn!`] but only logs the first time the call site is executed.
+#[macro_export]
+macro_rules! jwarn_once {
+    ($($arg:tt)*) => {{
+        static ONCE: std::sync::atomic::AtomicBool = std::sync::atomic::AtomicBool::new(false);
+        if !ONCE.swap(true, std::sync::atomic::Ordering::Relaxed) {
+            $crate::jwarn!($($arg)*);
+        }
+    }};
+}
+
+/// Same as [`jinfo!`] but only logs the first time the call site is executed.
+#[macro_export]
+macro_rules! jinfo_once {
+    ($($arg:tt)*) => {{
+        static ONCE: std::sync::atomic::AtomicBool = std::sync::atomic::AtomicBool::new(false);
+        if !ONCE.swap(true, std::sync::atomic::Ordering::Relaxed) {
+            $crate::jinfo!($($arg)*);
+        }
+    }};
+}
+
+/// Same as [`jdebug!`] but only logs the first time the call site is executed.
+#[macro_export]
+macro_rules! jdebug_once {
+    ($($arg:tt)*) => {{
+        static ONCE: std::sync::atomic::AtomicBool = std::sync::atomic::AtomicBool::new(false);
+        if !ONCE.swap(true, std::sync::atomic::Ordering::Relaxed) {
+            $crate::jdebug!($($arg)*);
+        }
+    }};
+}
+
+/// Same as [`jtrace!`] but only logs the first time the call site is executed.
+#[macro_export]
+macro_rules! jtrace_once {
+    ($($arg:tt)*) => {{
+        static ONCE: std::sync::atomic::AtomicBool = std::sync::atomic::AtomicBool::new(false);
+        if !ONCE.swap(true, std::sync::atomic::Ordering::Relaxed) {
+            $crate::jtrace!($($arg)*);
+        }
+    }};
+}
+
+/// Log a hexdump of a byte slice at DEBUG level, see [`hexdump`].
+/// The number of bytes per line (default 16) and whether to show the printable characters
+/// (default true) can be specified.
+///
+/// ```
+///     use jlogger::jhex;
+///
+///     let buf = [0x01u8, 0x02, 0x41, 0x42];
+///     jhex!(&buf);
+///     jhex!(&buf, 8);
+///     jhex!(&buf, 8, false);
+/// ```
+///
+/// >DEBUG : src/main.rs-4 : 4 bytes  
+/// >00000000  01 02 41 42                                      |..AB|
+#[macro_export]
+macro_rules! jhex {
+    ($data:expr) => {
+        $crate::jhex!($data, 16, true)
+    };
+    ($data:expr, $width:expr) => {
+        $crate::jhex!($data, $width, true)
+    };
+    ($data:expr, $width:expr, $ascii:expr) => {{
+        let data: &[u8] = $data;
+        log::debug!(
+            "{}-{} : {} bytes\n{}",
+            file!(),
+            line!(),
+            data.len(),
+            $crate::hexdump(data, $width, $ascii)
+        );
+    }};
+}
+
+/// Log the `Debug` representation of an expression at TRACE level and return its value, so that
+/// any sub expression can be inspected without restructuring the code. The expression is
+/// evaluated exactly once. A label can be given instead of the expression text.
+///
+/// ```
+///     use jlogger::jtap;
+///
+///     let len = jtap!("hello".len()) * 2;
+///     let sum = jtap!(len + 1, "sum");
+/// ```
+///
+/// >TRACE : src/main.rs-3 : "hello".len() = 5  
+/// >TRACE : src/main.rs-4 : sum = 11
+#[macro_export]
+macro_rules! jtap {
+    ($val:expr) => {
+        $crate::jtap!($val, stringify!($val))
+    };
+    ($val:expr, $label:expr) => {
+        match $val {
+            val => {
+                log::trace!("{}-{} : {} = {:?}", file!(), line!(), $label, &val);
+                val
+            }
+        }
+    };
+}
+
+/// Log the `Debug` representation of a value at DEBUG level on a single line, see
+/// [`jdebug_pretty!`] for the multi-line form.
+///
+/// ```
+///     use jlogger::jdebug_compact;
+///
+///     let ports = vec![80, 443];
+///     jdebug_compact!(&ports);
+/// ```
+///
+/// >DEBUG : src/main.rs-4 : &ports = [80, 443]
+#[macro_export]
+macro_rules! jdebug_compact {
+    ($val:expr) => {
+        log::debug!(
+            "{}-{} : {} = {:?}",
+            file!(),
+            line!(),
+            stringify!($val),
+            $val
+        );
+    };
+}
+
+/// Log the pretty-printed (multi-line, indented) `Debug` representation of a value at DEBUG
+/// level, handy for large structs and collections.
+///
+/// ```
+///     use jlogger::jdebug_pretty;
+///
+///     let ports = vec![80, 443];
+///     jdebug_pretty!(&ports);
+/// ```
+///
+/// >DEBUG : src/main.rs-4 : &ports = [  
+/// >    80,  
+/// >    443,  
+/// >]
+#[macro_export]
+macro_rules! jdebug_pretty {
+    ($val:expr) => {
+        log::debug!(
+            "{}-{} : {} = {:#?}",
+            file!(),
+            line!(),
+            stringify!($val),
             $val
         );
     };
-    ($fmt:expr,$($val:expr),*) => {{
-        log::warn!(
-            "{}-{} : {}",
-            file!(),
-            line!(),
-            format!($fmt, $($val),*)
+}
+
+/// Log an error together with the chain of its sources at ERROR level, see [`ErrorChain`].
+/// A `Box<dyn Error>` has to be passed as `err.as_ref()`.
+///
+/// ```
+///     use jlogger::jerror_chain;
+///
+///     fn connect() -> Result<(), Box<dyn std::error::Error>> {
+///         Err("connection refused".into())
+///     }
+///
+///     if let Err(e) = connect() {
+///         jerror_chain!(e.as_ref());
+///     }
+/// ```
+///
+/// >ERROR : src/main.rs-8 : connection refused
+#[macro_export]
+macro_rules! jerror_chain {
+    ($err:expr) => {
+        log::error!("{}-{} : {}", file!(), line!(), $crate::ErrorChain(&$err));
+    };
+}
+
+/// Log a message at the level derived from an HTTP status code, see [`level_for_status`].
+///
+/// ```
+///     use jlogger::jstatus;
+///
+///     let status = 503;
+///     jstatus!(status, "GET /api failed with {}", status);
+/// ```
+///
+/// >ERROR : src/main.rs-4 : GET /api failed with 503
+#[macro_export]
+macro_rules! jstatus {
+    ($code:expr, $($arg:tt)+) => {
+        if let Some(level) = $crate::level_for_status($code).to_level() {
+            log::log!(level, "{}-{} : {}", file!(), line!(), format_args!($($arg)+));
+        }
+    };
+}
+
+/// Like [`debug_assert!`], but log the failure at ERROR level instead of panicking.
+/// With `panic,` before the condition, it also panics after logging. The condition is only
+/// evaluated in debug builds, like [`debug_assert!`] the macro does nothing in release builds.
+///
+/// ```
+///     use jlogger::jdebug_assert;
+///
+///     let queue: Vec<u32> = Vec::new();
+///     jdebug_assert!(queue.len() < 100, "queue overflow: {}", queue.len());
+///     jdebug_assert!(queue.is_empty());
+///     jdebug_assert!(panic, queue.capacity() >= queue.len());
+/// ```
+///
+/// >ERROR : src/main.rs-4 : queue overflow: 120  
+/// >ERROR : src/main.rs-5 : assertion failed: queue.is_empty()
+#[macro_export]
+macro_rules! jdebug_assert {
+    (panic, $cond:expr $(,)?) => {
+        $crate::jdebug_assert!(panic, $cond, "assertion failed: {}", stringify!($cond))
+    };
+    (panic, $cond:expr, $($arg:tt)+) => {
+        if cfg!(debug_assertions) && !$cond {
+            let message = format!($($arg)+);
+            log::error!("{}-{} : {}", file!(), line!(), message);
+            panic!("{}", message);
+        }
+    };
+    ($cond:expr $(,)?) => {
+        $crate::jdebug_assert!($cond, "assertion failed: {}", stringify!($cond))
+    };
+    ($cond:expr, $($arg:tt)+) => {
+        if cfg!(debug_assertions) && !$cond {
+            log::error!("{}-{} : {}", file!(), line!(), format_args!($($arg)+));
+        }
+    };
+}
+
+#[test]
+fn test_debug_macro() {
+    use log::{debug, info};
+
+    let _guard = JloggerBuilder::new()
+        .max_level(LevelFilter::Debug)
+        .log_console(true)
+        .log_runtime(true)
+        .log_time(LogTimeFormat::TimeLocal)
+        .log_file(Some("/tmp/abc"), false)
+        .build();
+
+    jdebug!("test: {}", String::from("hello"));
+    jdebug!("this is debug");
+
+    std::thread::Builder::new()
+        .name("thread1".to_string())
+        .spawn(|| {
+            log::debug!(
+                "this is debug in the thread {}.",
+                std::thread::current().name().unwrap()
+            );
+            jinfo!("this is info in the thread.");
+        })
+        .unwrap()
+        .join()
+        .unwrap();
+
+    jerror!("this is error");
+    jinfo!("this is info");
+    std::thread::spawn(|| {
+        log::debug!(
+            "this is debug in the thread {}.",
+            std::thread::current()
+                .name()
+                .unwrap_or("No thread name set"),
+        );
+        jinfo!("this is info in the thread.");
+    })
+    .join()
+    .unwrap();
+    info!("this is info");
+    jdebug!();
+    debug!("default");
+}
+
+#[test]
+fn test_log_banner() {
+    let log_file = TestFile::new("log_banner.log");
+
+    let logger = log_file
+        .builder()
+        .max_level(LevelFilter::Debug)
+        .log_banner(true)
+        .version("1.2.3")
+        .into_logger();
+    logger.log_banner();
+
+    let content = log_file.read();
+    assert!(content.starts_with("INFO  : jlogger started: version=1.2.3 "));
+    assert!(content.contains("level=DEBUG"));
+    assert!(content.contains(log_file.path()));
+    assert!(!content.contains(" cwd="));
+}
+
+#[test]
+fn test_log_cwd() {
+    let log_file = TestFile::new("log_cwd.log");
+
+    let logger = log_file
+        .builder()
+        .log_banner(true)
+        .log_cwd(true)
+        .into_logger();
+    logger.log_banner();
+
+    let cwd = std::env::current_dir().unwrap();
+    assert!(log_file
+        .read()
+        .ends_with(&format!(" cwd={}\n", cwd.display())));
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn test_log_file_fifo() {
+    use std::io::Read;
+    use std::os::unix::fs::OpenOptionsExt;
+
+    let fifo = TestFile::new("log_file_fifo");
+    assert!(std::process::Command::new("mkfifo")
+        .arg(fifo.path())
+        .status()
+        .unwrap()
+        .success());
+
+    let mut reader = fs::OpenOptions::new()
+        .read(true)
+        .custom_flags(O_NONBLOCK)
+        .open(fifo.path())
+        .unwrap();
+
+    let logger = fifo.builder().into_logger();
+    logger.log(
+        &Record::builder()
+            .level(log::Level::Info)
+            .args(format_args!("to the fifo"))
+            .build(),
+    );
+    drop(logger);
+
+    let mut content = String::new();
+    reader.read_to_string(&mut content).unwrap();
+
+    assert_eq!(content, "INFO  : to the fifo\n");
+}
+
+#[cfg(test)]
+fn test_log(logger: &Jlogger, level: log::Level, message: &str) {
+    logger.log(
+        &Record::builder()
+            .level(level)
+            .target("jlogger_test")
+            .args(format_args!("{}", message))
+            .build(),
+    );
+}
+
+/// Guard of a logger which is not installed as the global logger.
+#[cfg(test)]
+fn test_guard(builder: JloggerBuilder) -> JloggerGuard {
+    JloggerGuard {
+        logger: Box::leak(Box::new(builder.into_logger())),
+    }
+}
+
+/// File of a test in the temporary directory, removed when dropped.
+#[cfg(test)]
+struct TestFile(std::path::PathBuf);
+
+#[cfg(test)]
+impl TestFile {
+    /// `jlogger_test_<name>` in the temporary directory, removed if left by a previous run.
+    fn new(name: &str) -> Self {
+        let path = std::env::temp_dir().join(format!("jlogger_test_{}", name));
+        let _ = fs::remove_file(&path);
+        TestFile(path)
+    }
+
+    fn path(&self) -> &str {
+        self.0.to_str().unwrap()
+    }
+
+    fn read(&self) -> String {
+        fs::read_to_string(&self.0).unwrap()
+    }
+
+    /// Builder of a logger writing to this file only.
+    fn builder(&self) -> JloggerBuilder {
+        JloggerBuilder::new()
+            .log_console(false)
+            .log_file(Some(self.path()), false)
+    }
+}
+
+#[cfg(test)]
+impl Drop for TestFile {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.0);
+    }
+}
+
+/// Empty directory of a test in the temporary directory, removed with its files when dropped.
+#[cfg(test)]
+struct TestDir(std::path::PathBuf);
+
+#[cfg(test)]
+impl TestDir {
+    /// `jlogger_test_<name>` in the temporary directory, emptied if left by a previous run.
+    fn new(name: &str) -> Self {
+        let path = std::env::temp_dir().join(format!("jlogger_test_{}", name));
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir(&path).unwrap();
+        TestDir(path)
+    }
+
+    fn path(&self) -> &std::path::Path {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+impl Drop for TestDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+/// Clock of a test, returning the time it was set to until it is advanced.
+/// Clones share the time, so that a test can advance the clock given to a logger.
+#[cfg(test)]
+#[derive(Clone)]
+struct FrozenClock(std::sync::Arc<Mutex<chrono::DateTime<chrono::Local>>>);
+
+#[cfg(test)]
+impl FrozenClock {
+    fn new(year: i32, month: u32, day: u32, hour: u32, min: u32, sec: u32) -> Self {
+        use chrono::TimeZone;
+
+        FrozenClock(std::sync::Arc::new(Mutex::new(
+            chrono::Local
+                .with_ymd_and_hms(year, month, day, hour, min, sec)
+                .unwrap(),
+        )))
+    }
+
+    fn advance(&self, duration: chrono::Duration) {
+        *self.0.lock().unwrap() += duration;
+    }
+}
+
+#[cfg(test)]
+impl Clock for FrozenClock {
+    fn now(&self) -> chrono::DateTime<chrono::Local> {
+        *self.0.lock().unwrap()
+    }
+}
+
+/// Log file writer of a test, counting the calls of [`LogWrite::sync_data`].
+#[cfg(test)]
+#[derive(Clone, Default)]
+struct SyncCounter(std::sync::Arc<std::sync::atomic::AtomicUsize>);
+
+#[cfg(test)]
+impl SyncCounter {
+    fn count(&self) -> usize {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+#[cfg(test)]
+impl Write for SyncCounter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+impl LogWrite for SyncCounter {
+    fn sync_data(&mut self) -> io::Result<()> {
+        self.0.fetch_add(1, Ordering::Relaxed);
+        Ok(())
+    }
+}
+
+#[test]
+fn test_level_style() {
+    for (style, expected) in [
+        (LevelStyle::Full, "INFO  : styled\n"),
+        (LevelStyle::Short, "I : styled\n"),
+        (LevelStyle::Symbol, "ℹ : styled\n"),
+    ] {
+        let log_file = TestFile::new(&format!("level_style_{:?}.log", style));
+
+        let logger = log_file.builder().level_style(style).into_logger();
+        test_log(&logger, log::Level::Info, "styled");
+
+        assert_eq!(log_file.read(), expected);
+    }
+}
+
+#[test]
+fn test_field_order() {
+    let log_file = TestFile::new("field_order.log");
+
+    let logger = log_file
+        .builder()
+        .log_time(LogTimeFormat::TimeStamp)
+        .field_order(&[
+            LogField::Level,
+            LogField::Timestamp,
+            LogField::Target,
+            LogField::Message,
+        ])
+        .into_logger();
+    test_log(&logger, log::Level::Info, "reordered");
+
+    let content = log_file.read();
+    let tokens: Vec<&str> = content.split_whitespace().collect();
+    assert_eq!(tokens[0], "INFO");
+    assert!(tokens[1].parse::<f64>().is_ok());
+    assert_eq!(tokens[2], "jlogger_test");
+    assert!(content.ends_with(": reordered\n"));
+}
+
+#[test]
+#[should_panic]
+fn test_field_order_without_message() {
+    let _ = JloggerBuilder::new().field_order(&[LogField::Level]);
+}
+
+#[test]
+fn test_dedup_by_hash() {
+    let log_file = TestFile::new("dedup_by_hash.log");
+
+    let logger = log_file
+        .builder()
+        .dedup_by_hash(Duration::from_secs(60))
+        .into_logger();
+
+    for (file, line) in [("src/a.rs", 10), ("src/b.rs", 20)] {
+        logger.log(
+            &Record::builder()
+                .level(log::Level::Info)
+                .file(Some(file))
+                .line(Some(line))
+                .args(format_args!("{}-{} : flooding", file, line))
+                .build(),
+        );
+    }
+    test_log(&logger, log::Level::Info, "different");
+
+    assert_eq!(
+        log_file.read(),
+        "INFO  : src/a.rs-10 : flooding\nINFO  : different\n"
+    );
+
+    logger.flush();
+    assert_eq!(
+        log_file.read(),
+        "INFO  : src/a.rs-10 : flooding\nINFO  : different\n\
+         INFO  : (suppressed 1 duplicates of \"flooding\")\n"
+    );
+}
+
+#[test]
+fn test_dedup_by_hash_window_closed() {
+    let log_file = TestFile::new("dedup_by_hash_window_closed.log");
+    let clock = FrozenClock::new(2022, 5, 17, 13, 0, 0);
+
+    let logger = log_file
+        .builder()
+        .dedup_by_hash(Duration::from_secs(60))
+        .clock(clock.clone())
+        .into_logger();
+    for _ in 0..3 {
+        test_log(&logger, log::Level::Warn, "flooding");
+    }
+    clock.advance(chrono::Duration::seconds(59));
+    logger.write_dedup_summaries(false);
+    assert_eq!(log_file.read(), "WARN  : flooding\n");
+
+    // The summary is outputted when the window closes, without waiting for the next message.
+    clock.advance(chrono::Duration::seconds(1));
+    logger.write_dedup_summaries(false);
+    test_log(&logger, log::Level::Info, "different");
+
+    assert_eq!(
+        log_file.read(),
+        "WARN  : flooding\n\
+         WARN  : (suppressed 2 duplicates of \"flooding\")\n\
+         INFO  : different\n"
+    );
+}
+
+#[test]
+fn test_burst_summary() {
+    let log_file = TestFile::new("burst_summary.log");
+
+    let clock = FrozenClock::new(2022, 5, 17, 13, 0, 0);
+
+    let logger = log_file
+        .builder()
+        .burst_summary(Duration::from_secs(60))
+        .clock(clock.clone())
+        .into_logger();
+
+    for line in 10..15 {
+        logger.log(
+            &Record::builder()
+                .level(log::Level::Error)
+                .file(Some("src/db.rs"))
+                .line(Some(line))
+                .args(format_args!("src/db.rs-{} : connection refused", line))
+                .build(),
+        );
+    }
+    clock.advance(chrono::Duration::seconds(59));
+    logger.write_burst_summaries(false);
+    assert_eq!(
+        log_file.read(),
+        "ERROR : src/db.rs-10 : connection refused\n"
+    );
+
+    clock.advance(chrono::Duration::seconds(1));
+    logger.write_burst_summaries(false);
+    test_log(&logger, log::Level::Info, "recovered");
+    test_log(&logger, log::Level::Error, "failed again");
+    logger.flush();
+
+    assert_eq!(
+        log_file.read(),
+        "ERROR : src/db.rs-10 : connection refused\n\
+         ERROR : (suppressed 4 duplicates of \"connection refused\", last: src/db.rs-14 : connection refused)\n\
+         INFO  : recovered\n\
+         ERROR : failed again\n"
+    );
+}
+
+#[test]
+fn test_console_device() {
+    let device = TestFile::new("console_device");
+    File::create(device.path()).unwrap();
+
+    let logger = JloggerBuilder::new()
+        .log_console(false)
+        .console_device(device.path())
+        .console_device("/nonexistent/jlogger/tty")
+        .into_logger();
+    test_log(&logger, log::Level::Warn, "to the device");
+
+    assert_eq!(device.read(), "WARN  : to the device\n");
+}
+
+#[test]
+fn test_display_duration() {
+    for (duration, expected) in [
+        (Duration::from_nanos(1_500_000), "1.5ms"),
+        (Duration::from_millis(1230), "1.23s"),
+        (Duration::from_millis(450), "450ms"),
+        (Duration::from_micros(12), "12µs"),
+        (Duration::from_nanos(80), "80ns"),
+        (Duration::from_secs(0), "0ns"),
+    ] {
+        assert_eq!(DisplayDuration(duration).to_string(), expected);
+    }
+}
+
+#[test]
+fn test_console_file_time() {
+    let device = TestFile::new("console_file_time_console");
+    File::create(device.path()).unwrap();
+    let log_file = TestFile::new("console_file_time.log");
+
+    let logger = log_file
+        .builder()
+        .console_device(device.path())
+        .log_time(LogTimeFormat::TimeStamp)
+        .console_time(LogTimeFormat::TimeNone)
+        .file_time(LogTimeFormat::TimeLocal)
+        .into_logger();
+    test_log(&logger, log::Level::Info, "timed");
+
+    assert_eq!(device.read(), "INFO  : timed\n");
+    let content = log_file.read();
+    assert!(chrono::NaiveDateTime::parse_from_str(&content[..19], "%Y-%m-%d %H:%M:%S").is_ok());
+    assert!(content.ends_with(" INFO  : timed\n"));
+}
+
+#[test]
+fn test_log_file_path_append() {
+    let log_file = TestFile::new("log_file_path_append.log");
+    fs::write(log_file.path(), "previous\n").unwrap();
+
+    let tuple = JloggerBuilder::new().log_file(Some(log_file.path()), true);
+    let separate = JloggerBuilder::new()
+        .log_file_append(true)
+        .log_file_path(log_file.path());
+    assert_eq!(tuple.log_file_name, separate.log_file_name);
+    assert_eq!(tuple.log_file_append, separate.log_file_append);
+
+    let logger = separate.log_console(false).into_logger();
+    test_log(&logger, log::Level::Info, "appended");
+
+    assert_eq!(log_file.read(), "previous\nINFO  : appended\n");
+}
+
+#[test]
+fn test_max_line_len_split() {
+    let log_file = TestFile::new("max_line_len_split.log");
+
+    let logger = log_file
+        .builder()
+        .max_line_len_split(48 * 1024)
+        .into_logger();
+    test_log(&logger, log::Level::Info, &"x".repeat(100 * 1024));
+    test_log(&logger, log::Level::Info, "short");
+
+    let content = log_file.read();
+    let lines: Vec<&str> = content.lines().collect();
+    assert_eq!(lines.len(), 4);
+    assert!(lines[0].ends_with(" (cont.) [split:1]"));
+    assert!(lines[1].ends_with(" (cont.) [split:1]"));
+    assert!(lines[2].ends_with("x [split:1]"));
+    assert_eq!(lines[3], "INFO  : short");
+    assert_eq!(content.matches('x').count(), 100 * 1024);
+}
+
+#[test]
+fn test_correlation_id() {
+    let log_file = TestFile::new("correlation_id.log");
+
+    let logger = log_file.builder().into_logger();
+    set_correlation_id("req-42");
+    test_log(&logger, log::Level::Info, "in request");
+    clear_correlation_id();
+    test_log(&logger, log::Level::Info, "out of request");
+
+    assert_eq!(
+        log_file.read(),
+        "INFO  [req-42] : in request\nINFO  : out of request\n"
+    );
+}
+
+#[test]
+fn test_conditional_buffer_scope() {
+    let log_file = TestFile::new("conditional_buffer_scope.log");
+
+    let logger = log_file.builder().into_logger();
+    {
+        let _scope = conditional_buffer_scope();
+        test_log(&logger, log::Level::Debug, "discarded");
+        test_log(&logger, log::Level::Info, "succeeded");
+    }
+    {
+        let _scope = conditional_buffer_scope();
+        test_log(&logger, log::Level::Debug, "step 1");
+        test_log(&logger, log::Level::Trace, "step 2");
+        test_log(&logger, log::Level::Error, "failed");
+        test_log(&logger, log::Level::Debug, "cleanup");
+    }
+    test_log(&logger, log::Level::Debug, "out of scope");
+
+    assert_eq!(
+        log_file.read(),
+        "INFO  : succeeded\n\
+         DEBUG : step 1\n\
+         TRACE : step 2\n\
+         ERROR : failed\n\
+         DEBUG : cleanup\n"
+    );
+}
+
+#[test]
+fn test_conditional_buffer_scope_filters() {
+    let log_file = TestFile::new("conditional_buffer_scope_filters.log");
+
+    let logger = log_file
+        .builder()
+        .mute_target("muted")
+        .target_level("quiet", LevelFilter::Info)
+        .filter(|m| m.target() != "filtered")
+        .into_logger();
+    {
+        let _scope = conditional_buffer_scope();
+        for target in ["muted", "quiet", "filtered", "jlogger_test"] {
+            logger.log(
+                &Record::builder()
+                    .level(log::Level::Debug)
+                    .target(target)
+                    .file(Some("src/main.rs"))
+                    .line(Some(12))
+                    .args(format_args!("src/main.rs-12 : from {}", target))
+                    .build(),
+            );
+        }
+        test_log(&logger, log::Level::Error, "failed");
+    }
+
+    assert_eq!(
+        log_file.read(),
+        "DEBUG : src/main.rs-12 : from jlogger_test\n\
+         ERROR : failed\n"
+    );
+}
+
+#[test]
+fn test_on_disk_full_drop() {
+    struct DiskFullWriter;
+
+    impl Write for DiskFullWriter {
+        fn write(&mut self, _buf: &[u8]) -> io::Result<usize> {
+            Err(io::Error::from(io::ErrorKind::StorageFull))
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl LogWrite for DiskFullWriter {}
+
+    let mut logger = JloggerBuilder::new()
+        .log_console(false)
+        .on_disk_full(DiskFullPolicy::Drop)
+        .into_logger();
+    logger.log_file = RwLock::new(Some(LogFile::new(
+        "disk_full".to_string(),
+        Box::new(DiskFullWriter),
+    )));
+    test_log(&logger, log::Level::Info, "disk is full");
+    test_log(&logger, log::Level::Info, "disk is still full");
+
+    assert_eq!(logger.dropped_records.load(Ordering::Relaxed), 2);
+}
+
+#[test]
+fn test_guard_set_log_file() {
+    let first = TestFile::new("guard_set_log_file_1.log");
+    let second = TestFile::new("guard_set_log_file_2.log");
+
+    let guard = test_guard(first.builder());
+    test_log(guard.logger, log::Level::Info, "before switch");
+    guard.set_log_file(second.path(), false).unwrap();
+    test_log(guard.logger, log::Level::Info, "after switch");
+    assert!(guard
+        .set_log_file("/nonexistent/jlogger.log", false)
+        .is_err());
+    test_log(guard.logger, log::Level::Info, "after failed switch");
+
+    assert_eq!(first.read(), "INFO  : before switch\n");
+    assert_eq!(
+        second.read(),
+        "INFO  : after switch\nINFO  : after failed switch\n"
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn test_guard_set_log_file_settings() {
+    let first = TestFile::new("guard_set_log_file_settings_1.log");
+    let _first_hourly = TestFile::new("guard_set_log_file_settings_1.log-20240115-13");
+    let second = TestFile::new("guard_set_log_file_settings_2.log");
+    let second_hourly = TestFile::new("guard_set_log_file_settings_2.log-20240115-13");
+
+    let guard = test_guard(
+        first
+            .builder()
+            .clock(FrozenClock::new(2024, 1, 15, 13, 0, 3))
+            .rotate_hourly(true)
+            .session_separator(true)
+            .exclusive_lock(true),
+    );
+    guard.set_log_file(second.path(), true).unwrap();
+    test_log(guard.logger, log::Level::Info, "after switch");
+
+    assert_eq!(
+        guard
+            .logger
+            .log_file
+            .read()
+            .unwrap()
+            .as_ref()
+            .unwrap()
+            .hourly_base,
+        Some(second.path().to_string())
+    );
+    assert_eq!(
+        open_locked_log_file(second_hourly.path(), true)
+            .unwrap_err()
+            .kind(),
+        io::ErrorKind::WouldBlock
+    );
+    assert_eq!(
+        second_hourly.read(),
+        format!(
+            "===== new session 2024-01-15 13:00:03 pid={} =====\nINFO  : after switch\n",
+            std::process::id()
+        )
+    );
+}
+
+#[test]
+fn test_level_counts() {
+    let guard = test_guard(JloggerBuilder::new().log_console(false));
+    test_log(guard.logger, log::Level::Error, "error 1");
+    test_log(guard.logger, log::Level::Error, "error 2");
+    test_log(guard.logger, log::Level::Info, "info");
+    test_log(guard.logger, log::Level::Debug, "filtered out");
+
+    assert_eq!(
+        guard.level_counts(),
+        LevelCounts {
+            error: 2,
+            info: 1,
+            ..Default::default()
+        }
+    );
+}
+
+#[test]
+fn test_prefix_multiline() {
+    let log_file = TestFile::new("prefix_multiline.log");
+
+    let logger = log_file.builder().prefix_multiline(true).into_logger();
+    logger.log(
+        &Record::builder()
+            .level(log::Level::Info)
+            .file(Some("src/main.rs"))
+            .line(Some(12))
+            .args(format_args!("src/main.rs-12 : first line\nsecond line"))
+            .build(),
+    );
+
+    assert_eq!(
+        log_file.read(),
+        "INFO  : src/main.rs-12 : first line\nINFO  : src/main.rs-12 : second line\n"
+    );
+}
+
+#[test]
+fn test_console_rate_limit() {
+    let device = TestFile::new("console_rate_limit_console");
+    File::create(device.path()).unwrap();
+    let log_file = TestFile::new("console_rate_limit.log");
+
+    let clock = FrozenClock::new(2022, 5, 17, 13, 0, 0);
+
+    let logger = log_file
+        .builder()
+        .console_device(device.path())
+        .console_rate_limit(10)
+        .clock(clock.clone())
+        .into_logger();
+    for i in 0..100 {
+        test_log(&logger, log::Level::Info, &format!("burst {}", i));
+    }
+    // Half a second refills 5 tokens.
+    clock.advance(chrono::Duration::milliseconds(500));
+    for i in 100..110 {
+        test_log(&logger, log::Level::Info, &format!("burst {}", i));
+    }
+
+    let console = device.read();
+    let console: Vec<&str> = console.lines().collect();
+    let passed: Vec<&str> = console
+        .iter()
+        .filter(|l| !l.contains("(console throttled)"))
+        .copied()
+        .collect();
+    assert_eq!(console.len(), 17);
+    assert_eq!(console[10], "WARN  : (console throttled)");
+    assert_eq!(console[16], "WARN  : (console throttled)");
+    assert_eq!(passed.len(), 15);
+    assert_eq!(passed[9], "INFO  : burst 9");
+    assert_eq!(passed[10], "INFO  : burst 100");
+    assert_eq!(passed[14], "INFO  : burst 104");
+    assert_eq!(log_file.read().lines().count(), 110);
+}
+
+#[test]
+fn test_clock() {
+    let log_file = TestFile::new("clock.log");
+
+    let logger = log_file
+        .builder()
+        .log_time(LogTimeFormat::TimeLocal)
+        .clock(FrozenClock::new(2024, 1, 15, 13, 0, 3))
+        .into_logger();
+    test_log(&logger, log::Level::Info, "frozen");
+
+    assert_eq!(log_file.read(), "2024-01-15 13:00:03 INFO  : frozen\n");
+}
+
+// SIGUSR1 is 16 on MIPS and 30 on SPARC.
+#[cfg(all(
+    target_os = "linux",
+    not(any(
+        target_arch = "mips",
+        target_arch = "mips64",
+        target_arch = "sparc",
+        target_arch = "sparc64"
+    ))
+))]
+#[test]
+fn test_reopen_signal() {
+    const SIGUSR1: std::os::raw::c_int = 10;
+
+    extern "C" {
+        fn raise(sig: std::os::raw::c_int) -> std::os::raw::c_int;
+    }
+
+    let log_audit = |logger: &Jlogger, message: &str| {
+        logger.log(
+            &Record::builder()
+                .level(log::Level::Info)
+                .target("audit")
+                .args(format_args!("{}", message))
+                .build(),
+        )
+    };
+    let log_file = TestFile::new("reopen_signal.log");
+    let rotated = TestFile::new("reopen_signal.log.1");
+    let audit_file = TestFile::new("reopen_signal_audit.log");
+    let audit_rotated = TestFile::new("reopen_signal_audit.log.1");
+
+    let mut builder = log_file
+        .builder()
+        .reopen_signal(SIGUSR1)
+        .route("audit", audit_file.builder());
+    builder.prepare().unwrap();
+    let logger = builder.into_logger();
+    test_log(&logger, log::Level::Info, "before reopen");
+    log_audit(&logger, "before reopen");
+    fs::rename(log_file.path(), rotated.path()).unwrap();
+    fs::rename(audit_file.path(), audit_rotated.path()).unwrap();
+    assert_eq!(unsafe { raise(SIGUSR1) }, 0);
+    test_log(&logger, log::Level::Info, "after reopen");
+    log_audit(&logger, "after reopen");
+
+    assert_eq!(rotated.read(), "INFO  : before reopen\n");
+    assert_eq!(log_file.read(), "INFO  : after reopen\n");
+    assert_eq!(audit_rotated.read(), "INFO  : before reopen\n");
+    assert_eq!(audit_file.read(), "INFO  : after reopen\n");
+}
+
+#[cfg(unix)]
+#[test]
+fn test_reopen_signal_invalid() {
+    // SIGKILL can not be caught.
+    let mut builder = JloggerBuilder::new().reopen_signal(9);
+    assert!(matches!(
+        builder.prepare(),
+        Err(JloggerError::InvalidSignal { signum: 9, .. })
+    ));
+}
+
+#[test]
+fn test_log_kmsg() {
+    let kmsg = TestFile::new("log_kmsg");
+    File::create(kmsg.path()).unwrap();
+
+    let mut builder = JloggerBuilder::new()
+        .log_console(false)
+        .log_time(LogTimeFormat::TimeLocal)
+        .log_kmsg(true);
+    builder.kmsg_path = kmsg.path().to_string();
+    let logger = builder.into_logger();
+    test_log(&logger, log::Level::Error, "to the kernel");
+    test_log(&logger, log::Level::Info, "info to the kernel");
+
+    assert_eq!(
+        kmsg.read(),
+        "<3>ERROR : to the kernel\n<6>INFO  : info to the kernel\n"
+    );
+}
+
+#[test]
+fn test_logcat_priority() {
+    // ANDROID_LOG_ERROR, ANDROID_LOG_WARN, ANDROID_LOG_INFO, ANDROID_LOG_DEBUG and
+    // ANDROID_LOG_VERBOSE.
+    for (level, prio) in [
+        (log::Level::Error, 6),
+        (log::Level::Warn, 5),
+        (log::Level::Info, 4),
+        (log::Level::Debug, 3),
+        (log::Level::Trace, 2),
+    ] {
+        assert_eq!(Jlogger::logcat_priority(level), prio);
+    }
+}
+
+#[test]
+fn test_eventlog_type() {
+    for (level, event_type) in [
+        (log::Level::Error, 0x0001),
+        (log::Level::Warn, 0x0002),
+        (log::Level::Info, 0x0004),
+        (log::Level::Debug, 0x0004),
+        (log::Level::Trace, 0x0004),
+    ] {
+        assert_eq!(Jlogger::eventlog_type(level), event_type);
+    }
+}
+
+#[test]
+fn test_lazy_file() {
+    let log_file = TestFile::new("lazy_file.log");
+
+    let logger = log_file.builder().lazy_file(true).into_logger();
+    test_log(&logger, log::Level::Debug, "filtered out");
+    assert!(!std::path::Path::new(log_file.path()).exists());
+
+    test_log(&logger, log::Level::Info, "first");
+    test_log(&logger, log::Level::Info, "second");
+    assert_eq!(log_file.read(), "INFO  : first\nINFO  : second\n");
+}
+
+#[test]
+fn test_lazy_file_unwritable() {
+    let log_file = TestFile::new("no_such_dir/lazy.log");
+
+    let mut builder = log_file.builder().lazy_file(true);
+    match builder.prepare() {
+        Err(JloggerError::Io { path, error }) => {
+            assert_eq!(path, log_file.path());
+            assert_eq!(error.kind(), io::ErrorKind::NotFound);
+        }
+        _ => panic!("unwritable lazy log file accepted"),
+    }
+
+    // Failing to open it later drops the log messages instead of panicking.
+    let logger = builder.into_logger();
+    test_log(&logger, log::Level::Info, "dropped");
+    assert_eq!(logger.dropped_records.load(Ordering::Relaxed), 1);
+}
+
+#[test]
+fn test_parse_level_filter() {
+    for (level, expected) in [
+        ("off", Some(LevelFilter::Off)),
+        ("none", Some(LevelFilter::Off)),
+        ("err", Some(LevelFilter::Error)),
+        ("ERROR", Some(LevelFilter::Error)),
+        ("warning", Some(LevelFilter::Warn)),
+        ("info", Some(LevelFilter::Info)),
+        ("Debug", Some(LevelFilter::Debug)),
+        ("verbose", Some(LevelFilter::Trace)),
+        ("bogus", None),
+    ] {
+        assert_eq!(parse_level_filter(level), expected);
+    }
+}
+
+#[test]
+fn test_record_channel() {
+    let (sender, receiver) = std::sync::mpsc::channel();
+    let logger = JloggerBuilder::new()
+        .log_console(false)
+        .record_channel(sender)
+        .into_logger();
+
+    let fields = [("id", 42)];
+    logger.log(
+        &Record::builder()
+            .level(log::Level::Warn)
+            .target("jlogger_test")
+            .file(Some("src/main.rs"))
+            .line(Some(7))
+            .key_values(&fields)
+            .args(format_args!("src/main.rs-7 : structured"))
+            .build(),
+    );
+
+    let record = receiver.try_recv().unwrap();
+    assert_eq!(record.level, log::Level::Warn);
+    assert_eq!(record.target, "jlogger_test");
+    assert_eq!(record.file.as_deref(), Some("src/main.rs"));
+    assert_eq!(record.line, Some(7));
+    assert_eq!(record.message, "structured");
+    assert_eq!(record.fields, vec![("id".to_string(), "42".to_string())]);
+}
+
+#[test]
+fn test_verbosity() {
+    for (verbosity, expected) in [
+        (0, LevelFilter::Warn),
+        (1, LevelFilter::Info),
+        (2, LevelFilter::Debug),
+        (3, LevelFilter::Trace),
+        (9, LevelFilter::Trace),
+    ] {
+        assert_eq!(
+            JloggerBuilder::new().verbosity(verbosity).max_level,
+            expected
+        );
+    }
+
+    assert_eq!(JloggerBuilder::new().quiet(1).max_level, LevelFilter::Error);
+    assert_eq!(JloggerBuilder::new().quiet(2).max_level, LevelFilter::Off);
+    assert_eq!(
+        JloggerBuilder::new().verbosity(2).quiet(0).max_level,
+        LevelFilter::Debug
+    );
+}
+
+#[test]
+fn test_write_retry() {
+    use std::sync::Arc;
+
+    struct FlakyWriter {
+        errors: Vec<io::ErrorKind>,
+        written: Arc<Mutex<Vec<u8>>>,
+    }
+
+    impl Write for FlakyWriter {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            match self.errors.pop() {
+                Some(kind) => Err(io::Error::from(kind)),
+                None => {
+                    self.written.lock().unwrap().extend_from_slice(buf);
+                    Ok(buf.len())
+                }
+            }
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl LogWrite for FlakyWriter {}
+
+    let written = Arc::new(Mutex::new(Vec::new()));
+    let mut logger = JloggerBuilder::new()
+        .log_console(false)
+        .write_retry(2, Duration::from_millis(1))
+        .into_logger();
+    logger.log_file = RwLock::new(Some(LogFile::new(
+        "flaky".to_string(),
+        Box::new(FlakyWriter {
+            // The interrupted write is not counted as an attempt.
+            errors: vec![
+                io::ErrorKind::Other,
+                io::ErrorKind::Interrupted,
+                io::ErrorKind::TimedOut,
+            ],
+            written: written.clone(),
+        }),
+    )));
+    test_log(&logger, log::Level::Info, "written at last");
+
+    assert_eq!(
+        String::from_utf8(written.lock().unwrap().clone()).unwrap(),
+        "INFO  : written at last\n"
+    );
+
+    // A full FIFO is not retried, the log message is dropped.
+    written.lock().unwrap().clear();
+    logger.log_file = RwLock::new(Some(LogFile::new(
+        "full_fifo".to_string(),
+        Box::new(FlakyWriter {
+            errors: vec![io::ErrorKind::WouldBlock],
+            written: written.clone(),
+        }),
+    )));
+    test_log(&logger, log::Level::Info, "dropped");
+
+    assert!(written.lock().unwrap().is_empty());
+    assert_eq!(logger.dropped_records.load(Ordering::Relaxed), 1);
+}
+
+#[test]
+fn test_fsync_interval() {
+    for (interval, expected) in [(Duration::ZERO, 3), (Duration::from_secs(3600), 1)] {
+        let syncs = SyncCounter::default();
+        let mut logger = JloggerBuilder::new()
+            .log_console(false)
+            .fsync_interval(interval)
+            .into_logger();
+        logger.log_file = RwLock::new(Some(LogFile::new(
+            "sync_counter".to_string(),
+            Box::new(syncs.clone()),
+        )));
+        for _ in 0..3 {
+            test_log(&logger, log::Level::Info, "synced");
+        }
+
+        assert_eq!(syncs.count(), expected);
+    }
+}
+
+#[test]
+fn test_path_components() {
+    let log_file = TestFile::new("path_components.log");
+
+    for (n, expected) in [
+        (1, "file.rs-12"),
+        (2, "sub/file.rs-12"),
+        (5, "src/module/sub/file.rs-12"),
+    ] {
+        let logger = log_file.builder().path_components(n).into_logger();
+        logger.log(
+            &Record::builder()
+                .level(log::Level::Info)
+                .file(Some("src/module/sub/file.rs"))
+                .line(Some(12))
+                .args(format_args!("src/module/sub/file.rs-12 : shortened"))
+                .build(),
+        );
+
+        assert_eq!(
+            log_file.read(),
+            format!("INFO  : {} : shortened\n", expected)
+        );
+    }
+}
+
+#[test]
+fn test_metadata_only() {
+    struct Panicking;
+
+    impl std::fmt::Display for Panicking {
+        fn fmt(&self, _f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            panic!("message evaluated");
+        }
+    }
+
+    let log_file = TestFile::new("metadata_only.log");
+
+    let logger = log_file.builder().metadata_only(true).into_logger();
+    logger.log(
+        &Record::builder()
+            .level(log::Level::Warn)
+            .file(Some("src/main.rs"))
+            .line(Some(12))
+            .args(format_args!("src/main.rs-12 : {}", Panicking))
+            .build(),
+    );
+    test_log(&logger, log::Level::Info, "no location");
+
+    assert_eq!(log_file.read(), "WARN  : src/main.rs-12\nINFO  : ?\n");
+}
+
+#[test]
+fn test_normalize_newlines() {
+    let log_file = TestFile::new("normalize_newlines.log");
+
+    let logger = log_file.builder().normalize_newlines(true).into_logger();
+    test_log(&logger, log::Level::Info, "no line break");
+    test_log(&logger, log::Level::Info, "two line breaks\n\n");
+    test_log(&logger, log::Level::Info, "crlf\r\n");
+
+    assert_eq!(
+        log_file.read(),
+        "INFO  : no line break\nINFO  : two line breaks\nINFO  : crlf\n"
+    );
+}
+
+#[test]
+fn test_redact() {
+    let device = TestFile::new("redact_console");
+    File::create(device.path()).unwrap();
+    let log_file = TestFile::new("redact.log");
+
+    let logger = log_file
+        .builder()
+        .console_device(device.path())
+        .redact("s3cr3t")
+        .redact("hunter2")
+        .into_logger();
+    test_log(&logger, log::Level::Info, "token=s3cr3t password=hunter2");
+
+    let expected = "INFO  : token=*** password=***\n";
+    assert_eq!(device.read(), expected);
+    assert_eq!(log_file.read(), expected);
+}
+
+#[test]
+fn test_filter() {
+    let log_file = TestFile::new("filter.log");
+
+    let logger = log_file
+        .builder()
+        .field_order(&[LogField::Target, LogField::Message])
+        .filter(|m| m.target().contains("::db"))
+        .into_logger();
+    for target in ["myapp::db", "myapp::db::pool", "myapp::http"] {
+        logger.log(
+            &Record::builder()
+                .level(log::Level::Info)
+                .target(target)
+                .args(format_args!("from {}", target))
+                .build(),
+        );
+    }
+
+    assert_eq!(
+        log_file.read(),
+        "myapp::db : from myapp::db\nmyapp::db::pool : from myapp::db::pool\n"
+    );
+}
+
+#[test]
+fn test_mute_target() {
+    let log_file = TestFile::new("mute_target.log");
+
+    let logger = log_file
+        .builder()
+        .field_order(&[LogField::Target, LogField::Message])
+        .mute_target("hyper")
+        .into_logger();
+    for target in ["hyper", "hyper::client", "hyperlocal", "myapp"] {
+        logger.log(
+            &Record::builder()
+                .level(log::Level::Info)
+                .target(target)
+                .args(format_args!("from {}", target))
+                .build(),
+        );
+    }
+
+    assert_eq!(
+        log_file.read(),
+        "hyperlocal : from hyperlocal\nmyapp : from myapp\n"
+    );
+}
+
+#[test]
+fn test_timestamp_base() {
+    let seconds = |base: TimeStampBase| {
+        let log_file = TestFile::new(&format!("timestamp_base_{:?}.log", base));
+
+        let logger = log_file
+            .builder()
+            .log_time(LogTimeFormat::TimeStamp)
+            .timestamp_base(base)
+            .into_logger();
+        test_log(&logger, log::Level::Info, "based");
+
+        let content = log_file.read();
+        content.split('.').next().unwrap().parse::<i64>().unwrap()
+    };
+
+    let epoch = chrono::Local::now().timestamp();
+    assert!((seconds(TimeStampBase::Epoch) - epoch).abs() <= 1);
+    assert!(seconds(TimeStampBase::Boot) < epoch - 1_000_000_000);
+}
+
+#[cfg(unix)]
+#[test]
+fn test_console_broken_pipe() {
+    let log_file = TestFile::new("console_broken_pipe.log");
+
+    let (reader, writer) = std::os::unix::net::UnixStream::pair().unwrap();
+    let logger = JloggerBuilder::new()
+        .log_console(true)
+        .console_writer(writer)
+        .log_file(Some(log_file.path()), false)
+        .into_logger();
+    drop(reader);
+
+    test_log(&logger, log::Level::Info, "reader gone");
+    assert!(logger.console_closed.load(Ordering::Relaxed));
+    test_log(&logger, log::Level::Info, "still logging");
+
+    assert_eq!(
+        log_file.read(),
+        "INFO  : reader gone\nINFO  : still logging\n"
+    );
+}
+
+#[test]
+fn test_log_thread_id() {
+    let log_file = TestFile::new("log_thread_id.log");
+
+    let logger = log_file.builder().log_thread_id(true).into_logger();
+
+    std::thread::scope(|s| {
+        for _ in 0..2 {
+            std::thread::Builder::new()
+                .name("worker".to_string())
+                .spawn_scoped(s, || test_log(&logger, log::Level::Info, "hello"))
+                .unwrap()
+                .join()
+                .unwrap();
+        }
+    });
+
+    let content = log_file.read();
+    let ids: Vec<&str> = content
+        .lines()
+        .map(|l| {
+            l.strip_prefix("INFO  worker#")
+                .and_then(|l| l.strip_suffix(" : hello"))
+                .unwrap()
+        })
+        .collect();
+    assert_eq!(ids.len(), 2);
+    assert_ne!(ids[0], ids[1]);
+}
+
+#[test]
+fn test_field_separator() {
+    let log_file = TestFile::new("field_separator.log");
+
+    let logger = log_file
+        .builder()
+        .log_time(LogTimeFormat::TimeLocal)
+        .field_separator("\t")
+        .clock(FrozenClock::new(2022, 5, 17, 13, 0, 6))
+        .into_logger();
+
+    test_log(&logger, log::Level::Info, "tab separated");
+
+    assert_eq!(
+        log_file.read(),
+        "2022-05-17 13:00:06\tINFO \t: tab separated\n"
+    );
+}
+
+#[test]
+fn test_target_level() {
+    let log_file = TestFile::new("target_level.log");
+
+    let logger = log_file
+        .builder()
+        .field_order(&[LogField::Target, LogField::Message])
+        .target_level("mycrate::net", LevelFilter::Debug)
+        .target_level("mycrate::net::tls", LevelFilter::Warn)
+        .into_logger();
+    for target in ["mycrate", "mycrate::net", "mycrate::net::tls"] {
+        logger.log(
+            &Record::builder()
+                .level(log::Level::Debug)
+                .target(target)
+                .args(format_args!("from {}", target))
+                .build(),
         );
-    }};
+    }
+
+    assert_eq!(log_file.read(), "mycrate::net : from mycrate::net\n");
+}
+
+#[test]
+fn test_strip_ansi_file() {
+    let log_file = TestFile::new("strip_ansi_file.log");
+    let console_file = TestFile::new("strip_ansi_file.console");
+    File::create(console_file.path()).unwrap();
+
+    let logger = log_file
+        .builder()
+        .console_device(console_file.path())
+        .into_logger();
+    test_log(&logger, log::Level::Info, "\x1b[1;31mred\x1b[0m alert");
+
+    assert_eq!(console_file.read(), "INFO  : \x1b[1;31mred\x1b[0m alert\n");
+    assert_eq!(log_file.read(), "INFO  : red alert\n");
+}
+
+#[test]
+fn test_level_names() {
+    let log_file = TestFile::new("level_names.log");
+
+    let logger = log_file
+        .builder()
+        .level_names(&[(log::Level::Error, "CRIT")])
+        .into_logger();
+    test_log(&logger, log::Level::Error, "disk failure");
+    test_log(&logger, log::Level::Warn, "disk almost full");
+
+    assert_eq!(
+        log_file.read(),
+        "CRIT : disk failure\nWARN  : disk almost full\n"
+    );
+}
+
+#[test]
+fn test_log_seq() {
+    let log_file = TestFile::new("log_seq.log");
+
+    let logger = log_file.builder().log_seq(true).into_logger();
+    for msg in ["one", "two", "three"] {
+        test_log(&logger, log::Level::Info, msg);
+    }
+
+    assert_eq!(
+        log_file.read(),
+        "#1 INFO  : one\n#2 INFO  : two\n#3 INFO  : three\n"
+    );
+}
+
+#[test]
+fn test_preset() {
+    let builder = JloggerBuilder::new().preset(Preset::DevFriendly);
+    assert_eq!(builder.max_level, LevelFilter::Debug);
+    assert!(builder.log_console);
+    assert!(builder.log_runtime);
+    assert_eq!(builder.console_time_format, Some(LogTimeFormat::TimeStamp));
+    assert_eq!(builder.file_time_format, Some(LogTimeFormat::TimeLocal));
+
+    let builder = JloggerBuilder::new().preset(Preset::Production);
+    assert_eq!(builder.max_level, LevelFilter::Info);
+    assert_eq!(builder.time_format, LogTimeFormat::TimeLocal);
+    assert!(builder.log_banner);
+
+    let builder = JloggerBuilder::new()
+        .log_runtime(true)
+        .preset(Preset::Compact)
+        .log_time(LogTimeFormat::TimeStamp);
+    assert!(!builder.log_runtime);
+    assert_eq!(builder.level_style, LevelStyle::Short);
+    assert_eq!(builder.time_format, LogTimeFormat::TimeStamp);
+}
+
+#[test]
+fn test_console_writer() {
+    #[derive(Clone, Default)]
+    struct Captured(std::sync::Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Captured {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    let captured = Captured::default();
+    let logger = JloggerBuilder::new()
+        .console_writer(captured.clone())
+        .into_logger();
+    test_log(&logger, log::Level::Info, "captured");
+    test_log(&logger, log::Level::Debug, "filtered");
+
+    assert_eq!(
+        String::from_utf8(captured.0.lock().unwrap().clone()).unwrap(),
+        "INFO  : captured\n"
+    );
+}
+
+#[test]
+fn test_log_utc_offset() {
+    let log_file = TestFile::new("log_utc_offset.log");
+    let clock = FrozenClock::new(2024, 1, 15, 13, 0, 3);
+
+    let logger = log_file
+        .builder()
+        .log_time(LogTimeFormat::TimeLocal)
+        .log_utc_offset(true)
+        .clock(clock.clone())
+        .into_logger();
+    test_log(&logger, log::Level::Info, "with offset");
+
+    assert_eq!(
+        log_file.read(),
+        format!(
+            "2024-01-15 13:00:03 {} INFO  : with offset\n",
+            clock.now().offset()
+        )
+    );
+}
+
+#[test]
+fn test_pause_file() {
+    for (pause_buffer, expected) in [
+        (0, "INFO  : before pause\nINFO  : after resume\n"),
+        (
+            1,
+            "INFO  : before pause\nINFO  : paused 1\nINFO  : after resume\n",
+        ),
+    ] {
+        let log_file = TestFile::new(&format!("pause_file_{}.log", pause_buffer));
+        let console_file = TestFile::new(&format!("pause_file_{}.console", pause_buffer));
+
+        let guard = test_guard(
+            JloggerBuilder::new()
+                .log_console(true)
+                .console_writer(File::create(console_file.path()).unwrap())
+                .log_file(Some(log_file.path()), false)
+                .pause_buffer(pause_buffer),
+        );
+        test_log(guard.logger, log::Level::Info, "before pause");
+        guard.pause_file();
+        test_log(guard.logger, log::Level::Info, "paused 1");
+        test_log(guard.logger, log::Level::Info, "paused 2");
+        guard.resume_file();
+        test_log(guard.logger, log::Level::Info, "after resume");
+
+        assert_eq!(log_file.read(), expected);
+        assert_eq!(
+            console_file.read(),
+            "INFO  : before pause\nINFO  : paused 1\nINFO  : paused 2\nINFO  : after resume\n"
+        );
+    }
+}
+
+#[test]
+fn test_epoch_nanos() {
+    let log_file = TestFile::new("epoch_nanos.log");
+
+    let logger = log_file
+        .builder()
+        .log_time(LogTimeFormat::EpochNanos)
+        .into_logger();
+    test_log(&logger, log::Level::Info, "nanos");
+
+    let log = log_file.read();
+    let (nanos, rest) = log.split_once(' ').unwrap();
+    assert_eq!(nanos.len(), 19);
+    assert!(nanos.parse::<i64>().is_ok());
+    assert_eq!(rest, "INFO  : nanos\n");
+}
+
+#[test]
+fn test_relative_time() {
+    let log_file = TestFile::new("relative_time.log");
+    let clock = FrozenClock::new(2022, 5, 17, 13, 0, 0);
+
+    let mut builder = log_file
+        .builder()
+        .log_time(LogTimeFormat::Relative)
+        .clock(clock.clone());
+    // The process started before the logger was built.
+    builder.process_age = || Some(Duration::from_millis(2500));
+    let logger = builder.into_logger();
+    test_log(&logger, log::Level::Info, "first");
+    clock.advance(chrono::Duration::milliseconds(1500));
+    test_log(&logger, log::Level::Info, "second");
+
+    assert_eq!(
+        log_file.read(),
+        "+2.500s INFO  : first\n+4.000s INFO  : second\n"
+    );
 }
 
-#[macro_export]
-macro_rules! jinfo{
-    () => {
-        log::info!(
-            "{}-{} : arrived.",
-            file!(),
-            line!(),
-        );
-    };
-    ($val:tt) => {
-        log::info!(
-            "{}-{} : {}",
-            file!(),
-            line!(),
-            $val
-        );
+#[cfg(target_os = "linux")]
+#[test]
+fn test_read_process_age() {
+    let age = read_process_age().unwrap();
+    // The test process started recently.
+    assert!(age < Duration::from_secs(24 * 3600));
+}
+
+#[cfg(unix)]
+#[test]
+fn test_log_unix_socket() {
+    use std::os::unix::net::UnixListener;
+
+    // Fail instead of hanging the test run if the logger does not connect or send.
+    let read_line = |listener: &UnixListener| {
+        listener.set_nonblocking(true).unwrap();
+        let stream = (0..500)
+            .find_map(|_| match listener.accept() {
+                Ok((stream, _)) => Some(stream),
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                    std::thread::sleep(Duration::from_millis(10));
+                    None
+                }
+                Err(e) => panic!("accept failed: {}", e),
+            })
+            .expect("the logger did not connect");
+        stream.set_nonblocking(false).unwrap();
+        stream
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+
+        let mut line = String::new();
+        BufReader::new(stream).read_line(&mut line).unwrap();
+        line
     };
-    ($fmt:expr,$($val:expr),*) => {{
-        log::info!(
-            "{}-{} : {}",
-            file!(),
-            line!(),
-            format!($fmt, $($val),*)
-        );
-    }};
+    let socket = TestFile::new("log_unix_socket.sock");
+    let listener = UnixListener::bind(socket.path()).unwrap();
+
+    let logger = JloggerBuilder::new()
+        .log_console(false)
+        .log_unix_socket(socket.path())
+        .into_logger();
+    test_log(&logger, log::Level::Info, "to the collector");
+    assert_eq!(read_line(&listener), "INFO  : to the collector\n");
+
+    // The collector restarted, the next log message goes to the new connection.
+    drop(listener);
+    fs::remove_file(socket.path()).unwrap();
+    let listener = UnixListener::bind(socket.path()).unwrap();
+    test_log(&logger, log::Level::Info, "after restart");
+    assert_eq!(read_line(&listener), "INFO  : after restart\n");
 }
 
-#[macro_export]
-macro_rules! jdebug {
-    () => {
-        log::debug!(
-            "{}-{} : arrived.",
-            file!(),
-            line!(),
+#[test]
+fn test_console_wrap() {
+    let log_file = TestFile::new("console_wrap.log");
+    let console_file = TestFile::new("console_wrap.console");
+    let device = TestFile::new("console_wrap_device");
+    File::create(device.path()).unwrap();
+
+    let mut builder = JloggerBuilder::new()
+        .console_writer(File::create(console_file.path()).unwrap())
+        .console_device(device.path())
+        .console_wrap(true)
+        .log_file(Some(log_file.path()), false);
+    builder.console_width = Some(20);
+    let logger = builder.into_logger();
+    test_log(
+        &logger,
+        log::Level::Info,
+        "a long message wrapped at the width, abcdefghijklmnopqrstuvwxyz",
+    );
+
+    assert_eq!(
+        console_file.read(),
+        "INFO  : a long\n    message wrapped\n    at the width,\n    abcdefghijklmnop\n    qrstuvwxyz\n"
+    );
+    assert_eq!(
+        log_file.read(),
+        "INFO  : a long message wrapped at the width, abcdefghijklmnopqrstuvwxyz\n"
+    );
+    assert_eq!(device.read(), log_file.read());
+}
+
+#[test]
+fn test_wrap_lines_colors() {
+    assert_eq!(
+        wrap_lines("\x1b[31mERROR\x1b[0m : abc def\n", 12),
+        "\x1b[31mERROR\x1b[0m : abc\n    def\n"
+    );
+    assert_eq!(
+        wrap_lines("ERROR : abc def\n", 12),
+        "ERROR : abc\n    def\n"
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn test_exclusive_lock() {
+    let log_file = TestFile::new("exclusive_lock.log");
+
+    let builder = || log_file.builder().exclusive_lock(true);
+
+    let mut first = builder();
+    first.prepare_log_file().unwrap();
+    let mut second = builder();
+    match second.prepare_log_file() {
+        Err(JloggerError::LogFileLocked { path }) => assert_eq!(path, log_file.path()),
+        _ => panic!("second lock of {} succeeded", log_file.path()),
+    }
+
+    let logger = first.into_logger();
+    test_log(&logger, log::Level::Info, "locked");
+    assert_eq!(log_file.read(), "INFO  : locked\n");
+}
+
+#[test]
+fn test_console_batch() {
+    #[derive(Clone, Default)]
+    struct CountingWriter(std::sync::Arc<Mutex<Vec<String>>>);
+
+    impl Write for CountingWriter {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0
+                .lock()
+                .unwrap()
+                .push(String::from_utf8_lossy(buf).into_owned());
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    let writes = CountingWriter::default();
+    let logger = JloggerBuilder::new()
+        .console_writer(writes.clone())
+        .console_batch(Duration::from_secs(3600))
+        .into_logger();
+    for i in 0..10 {
+        test_log(&logger, log::Level::Info, &format!("burst {}", i));
+    }
+    assert!(writes.0.lock().unwrap().is_empty());
+
+    test_log(&logger, log::Level::Error, "failure");
+    test_log(&logger, log::Level::Info, "after failure");
+    logger.flush();
+
+    let writes = writes.0.lock().unwrap();
+    assert_eq!(writes.len(), 2);
+    assert_eq!(writes[0].lines().count(), 11);
+    assert!(writes[0].ends_with("ERROR : failure\n"));
+    assert_eq!(writes[1], "INFO  : after failure\n");
+}
+
+#[test]
+fn test_set_time_format() {
+    let log_file = TestFile::new("set_time_format.log");
+
+    let guard = test_guard(log_file.builder());
+    test_log(guard.logger, log::Level::Info, "without time");
+    guard.set_time_format(LogTimeFormat::TimeLocal);
+    test_log(guard.logger, log::Level::Info, "with time");
+
+    let content = log_file.read();
+    let lines: Vec<&str> = content.lines().collect();
+    assert_eq!(lines[0], "INFO  : without time");
+    assert!(chrono::NaiveDateTime::parse_from_str(&lines[1][..19], "%Y-%m-%d %H:%M:%S").is_ok());
+    assert!(lines[1].ends_with(" INFO  : with time"));
+}
+
+#[test]
+fn test_target_version() {
+    let log_file = TestFile::new("target_version.log");
+
+    let logger = log_file
+        .builder()
+        .target_version("mycrate", "1.2.3")
+        .into_logger();
+    for target in ["mycrate::net", "othercrate"] {
+        logger.log(
+            &Record::builder()
+                .level(log::Level::Info)
+                .target(target)
+                .args(format_args!("from {}", target))
+                .build(),
         );
-    };
-    ($val:tt) => {
-        log::debug!(
-            "{}-{} : {}",
-            file!(),
-            line!(),
-            $val
+    }
+
+    assert_eq!(
+        log_file.read(),
+        "INFO  mycrate@1.2.3 : from mycrate::net\nINFO  : from othercrate\n"
+    );
+}
+
+#[test]
+fn test_boot_time_lazy() {
+    static READS: AtomicU64 = AtomicU64::new(0);
+
+    fn counting_boot_time() -> Option<i64> {
+        READS.fetch_add(1, Ordering::Relaxed);
+        Some(0)
+    }
+
+    let log_file = TestFile::new("boot_time_lazy.log");
+
+    let mut builder = log_file.builder().log_time(LogTimeFormat::TimeLocal);
+    builder.boot_time = counting_boot_time;
+    let guard = test_guard(builder);
+    test_log(guard.logger, log::Level::Info, "local time");
+    assert_eq!(READS.load(Ordering::Relaxed), 0);
+
+    guard.set_time_format(LogTimeFormat::TimeStamp);
+    test_log(guard.logger, log::Level::Info, "time stamp");
+    test_log(guard.logger, log::Level::Info, "time stamp");
+    assert_eq!(READS.load(Ordering::Relaxed), 1);
+}
+
+#[test]
+fn test_template() {
+    let log_file = TestFile::new("template.log");
+    let console_file = TestFile::new("template.console");
+
+    let logger = JloggerBuilder::new()
+        .console_writer(File::create(console_file.path()).unwrap())
+        .log_file(Some(log_file.path()), false)
+        .console_template("{level} {msg}")
+        .file_template("[{target}] {{{file}:{line}}} {msg}")
+        .into_logger();
+    logger.log(
+        &Record::builder()
+            .level(log::Level::Info)
+            .target("myapp")
+            .file(Some("src/main.rs"))
+            .line(Some(12))
+            .args(format_args!("src/main.rs-12 : templated"))
+            .build(),
+    );
+
+    assert_eq!(console_file.read(), "INFO  templated\n");
+    assert_eq!(log_file.read(), "[myapp] {src/main.rs:12} templated\n");
+
+    match JloggerBuilder::new()
+        .file_template("{time} {message}")
+        .prepare()
+    {
+        Err(JloggerError::InvalidTemplate { placeholder, .. }) => {
+            assert_eq!(placeholder, "message")
+        }
+        _ => panic!("unknown placeholder accepted"),
+    }
+}
+
+#[test]
+fn test_file_lock_mode() {
+    let log_file = TestFile::new("file_lock_mode.log");
+
+    let logger = log_file
+        .builder()
+        .file_lock_mode(LockMode::TrySkip)
+        .into_logger();
+    test_log(&logger, log::Level::Info, "first");
+    {
+        // Blocking on the lock held by this thread would never return.
+        let _held = logger.log_file.write().unwrap();
+        test_log(&logger, log::Level::Info, "skipped");
+    }
+    test_log(&logger, log::Level::Info, "last");
+
+    assert_eq!(logger.dropped_records.load(Ordering::Relaxed), 1);
+    assert_eq!(log_file.read(), "INFO  : first\nINFO  : last\n");
+}
+
+#[test]
+fn test_stats_interval() {
+    let log_file = TestFile::new("stats_interval.log");
+    let clock = FrozenClock::new(2022, 5, 17, 13, 0, 0);
+
+    let guard = test_guard(
+        log_file
+            .builder()
+            .stats_interval(Duration::from_secs(60))
+            .clock(clock.clone()),
+    );
+    let logger = guard.logger;
+    let interval = Duration::from_secs(60);
+    let mut last = Duration::ZERO;
+    test_log(logger, log::Level::Warn, "warned");
+
+    logger.stats_tick(interval, &mut last);
+    clock.advance(chrono::Duration::seconds(59));
+    logger.stats_tick(interval, &mut last);
+    assert_eq!(log_file.read(), "WARN  : warned\n");
+
+    clock.advance(chrono::Duration::seconds(1));
+    logger.stats_tick(interval, &mut last);
+    clock.advance(chrono::Duration::seconds(30));
+    logger.stats_tick(interval, &mut last);
+    assert_eq!(
+        log_file.read(),
+        "WARN  : warned\n\
+         INFO  : jlogger stats: error=0 warn=1 info=0 debug=0 trace=0 dropped=0 bytes=15\n"
+    );
+
+    drop(guard);
+    assert!(logger.stopped.load(Ordering::Relaxed));
+}
+
+#[test]
+fn test_session_separator() {
+    let log_file = TestFile::new("session_separator.log");
+
+    for run in ["first run", "second run"] {
+        let logger = JloggerBuilder::new()
+            .log_console(false)
+            .log_file(Some(log_file.path()), true)
+            .session_separator(true)
+            .into_logger();
+        test_log(&logger, log::Level::Info, run);
+    }
+
+    let log = log_file.read();
+    let lines: Vec<&str> = log.lines().collect();
+    assert_eq!(lines.len(), 4);
+    for separator in [lines[0], lines[2]] {
+        assert!(separator.starts_with("===== new session "));
+        assert!(separator.ends_with(&format!(" pid={} =====", std::process::id())));
+    }
+    assert_eq!(lines[1], "INFO  : first run");
+    assert_eq!(lines[3], "INFO  : second run");
+}
+
+#[test]
+fn test_try_build_unwritable_log_file() {
+    let log_file = TestFile::new("no_such_dir/test.log");
+
+    match JloggerBuilder::new()
+        .log_file(Some(log_file.path()), false)
+        .prepare()
+    {
+        Err(JloggerError::Io { path, error }) => {
+            assert_eq!(path, log_file.path());
+            assert_eq!(error.kind(), io::ErrorKind::NotFound);
+        }
+        _ => panic!("unwritable log file accepted"),
+    }
+}
+
+#[test]
+fn test_guard_flush_on_drop() {
+    let console_file = TestFile::new("guard_flush_on_drop.console");
+
+    let guard = test_guard(
+        JloggerBuilder::new()
+            .console_writer(File::create(console_file.path()).unwrap())
+            .console_batch(Duration::from_secs(60)),
+    );
+    test_log(guard.logger, log::Level::Info, "batched");
+    assert_eq!(console_file.read(), "");
+
+    drop(guard);
+    assert_eq!(console_file.read(), "INFO  : batched\n");
+}
+
+#[test]
+fn test_route() {
+    let app_file = TestFile::new("route_app.log");
+    let audit_file = TestFile::new("route_audit.log");
+
+    let logger = app_file
+        .builder()
+        .route(
+            "audit",
+            audit_file
+                .builder()
+                .field_order(&[LogField::Target, LogField::Message]),
+        )
+        .into_logger();
+    for target in ["audit", "audit::login", "auditor", "myapp"] {
+        logger.log(
+            &Record::builder()
+                .level(log::Level::Info)
+                .target(target)
+                .args(format_args!("from {}", target))
+                .build(),
         );
+    }
+
+    assert_eq!(
+        app_file.read(),
+        "INFO  : from auditor\nINFO  : from myapp\n"
+    );
+    assert_eq!(
+        audit_file.read(),
+        "audit : from audit\naudit::login : from audit::login\n"
+    );
+}
+
+#[test]
+fn test_memory_ring() {
+    let guard = test_guard(
+        JloggerBuilder::new()
+            .log_console(false)
+            .memory_ring(100)
+            .field_order(&[LogField::Message]),
+    );
+    let logger = guard.logger;
+
+    let writers: Vec<_> = (0..4)
+        .map(|t| {
+            std::thread::spawn(move || {
+                for i in 0..1000 {
+                    test_log(logger, log::Level::Info, &format!("{} {}", t, i));
+                }
+            })
+        })
+        .collect();
+
+    // Every snapshot keeps the log messages of each writer in order and without gaps.
+    let check = |snapshot: &[String]| {
+        assert!(snapshot.len() <= 100);
+        let mut last: [Option<u32>; 4] = [None; 4];
+        for m in snapshot {
+            let (t, i) = m.strip_prefix(": ").unwrap().split_once(' ').unwrap();
+            let (t, i): (usize, u32) = (t.parse().unwrap(), i.parse().unwrap());
+            assert!(last[t].is_none_or(|l| l + 1 == i));
+            last[t] = Some(i);
+        }
     };
-    ($fmt:expr,$($val:expr),*) => {{
-        log::debug!(
-            "{}-{} : {}",
-            file!(),
-            line!(),
-            format!($fmt, $($val),*)
-        );
-    }};
+    while writers.iter().any(|w| !w.is_finished()) {
+        check(&guard.snapshot());
+    }
+    for w in writers {
+        w.join().unwrap();
+    }
+
+    let snapshot = guard.snapshot();
+    check(&snapshot);
+    assert_eq!(snapshot.len(), 100);
 }
 
-#[macro_export]
-macro_rules! jtrace {
-    () => {
-        log::trace!(
-            "{}-{} : arrived.",
-            file!(),
-            line!(),
+#[test]
+fn test_memory_ring_zero_capacity() {
+    let guard = test_guard(JloggerBuilder::new().log_console(false).memory_ring(0));
+    test_log(guard.logger, log::Level::Info, "not kept");
+
+    assert!(guard.logger.memory_ring.is_none());
+    assert!(guard.snapshot().is_empty());
+}
+
+#[test]
+fn test_severity_map() {
+    let log_file = TestFile::new("severity_map.log");
+
+    let logger = log_file
+        .builder()
+        .severity_class("myapp::auth", "security")
+        .level_severity_class(log::Level::Error, "incident")
+        .into_logger();
+    for (target, level) in [
+        ("audit::login", log::Level::Info),
+        ("myapp::auth", log::Level::Warn),
+        ("myapp::db", log::Level::Error),
+        ("myapp::http", log::Level::Info),
+    ] {
+        logger.log(
+            &Record::builder()
+                .level(level)
+                .target(target)
+                .args(format_args!("from {}", target))
+                .build(),
         );
-    };
-    ($val:tt) => {
-        log::trace!(
-            "{}-{} : {}",
-            file!(),
-            line!(),
-            $val
+    }
+
+    assert_eq!(
+        log_file.read(),
+        "INFO  severity=audit : from audit::login\n\
+         WARN  severity=security : from myapp::auth\n\
+         ERROR severity=incident : from myapp::db\n\
+         INFO  severity=operational : from myapp::http\n"
+    );
+}
+
+#[test]
+fn test_color_targets() {
+    let log_file = TestFile::new("color_targets.log");
+    let console_file = TestFile::new("color_targets.console");
+
+    let logger = JloggerBuilder::new()
+        .console_writer(File::create(console_file.path()).unwrap())
+        .log_file(Some(log_file.path()), false)
+        .field_order(&[LogField::Target, LogField::Message])
+        .color_targets(true)
+        .into_logger();
+    for target in ["myapp::db", "myapp::http", "myapp::db"] {
+        logger.log(
+            &Record::builder()
+                .level(log::Level::Info)
+                .target(target)
+                .args(format_args!("colored"))
+                .build(),
         );
+    }
+
+    let console = console_file.read();
+    let lines: Vec<&str> = console.lines().collect();
+    assert_eq!(
+        lines[0],
+        format!(
+            "\x1b[{}mmyapp::db\x1b[0m : colored",
+            target_color("myapp::db")
+        )
+    );
+    assert_eq!(lines[2], lines[0]);
+    assert!(lines[1].starts_with("\x1b["));
+    assert_eq!(
+        log_file.read(),
+        "myapp::db : colored\nmyapp::http : colored\nmyapp::db : colored\n"
+    );
+}
+
+#[test]
+fn test_heartbeat() {
+    let log_file = TestFile::new("heartbeat.log");
+    let clock = FrozenClock::new(2022, 5, 17, 13, 0, 0);
+    let advance = |secs| clock.advance(chrono::Duration::seconds(secs));
+
+    let guard = test_guard(
+        log_file
+            .builder()
+            .heartbeat(Duration::from_secs(60))
+            .clock(clock.clone()),
+    );
+    let logger = guard.logger;
+    let interval = Duration::from_secs(60);
+
+    advance(20);
+    assert_eq!(logger.heartbeat_tick(interval), Duration::from_secs(40));
+    advance(40);
+    assert_eq!(logger.heartbeat_tick(interval), interval);
+    advance(50);
+    test_log(logger, log::Level::Info, "busy");
+    advance(30);
+    assert_eq!(logger.heartbeat_tick(interval), Duration::from_secs(30));
+    advance(30);
+    assert_eq!(logger.heartbeat_tick(interval), interval);
+    assert_eq!(
+        log_file.read(),
+        "INFO  : heartbeat\nINFO  : busy\nINFO  : heartbeat\n"
+    );
+
+    drop(guard);
+    assert!(logger.stopped.load(Ordering::Relaxed));
+}
+
+#[test]
+fn test_rotate_hourly() {
+    let log_file = TestFile::new("rotate_hourly.log");
+    let first_hour = TestFile::new("rotate_hourly.log-20240115-13");
+    let second_hour = TestFile::new("rotate_hourly.log-20240115-14");
+    let clock = FrozenClock::new(2024, 1, 15, 13, 59, 59);
+
+    let logger = log_file
+        .builder()
+        .log_time(LogTimeFormat::TimeNone)
+        .clock(clock.clone())
+        .rotate_hourly(true)
+        .into_logger();
+
+    test_log(&logger, log::Level::Info, "first hour");
+    clock.advance(chrono::Duration::seconds(1));
+    test_log(&logger, log::Level::Info, "second hour");
+    test_log(&logger, log::Level::Info, "still second hour");
+
+    assert_eq!(first_hour.read(), "INFO  : first hour\n");
+    assert_eq!(
+        second_hour.read(),
+        "INFO  : second hour\nINFO  : still second hour\n"
+    );
+}
+
+#[test]
+fn test_guard_flush_all() {
+    let console_file = TestFile::new("guard_flush_all.console");
+    let syncs = SyncCounter::default();
+
+    let mut logger = JloggerBuilder::new()
+        .console_writer(File::create(console_file.path()).unwrap())
+        .console_batch(Duration::from_secs(60))
+        .into_logger();
+    logger.log_file = RwLock::new(Some(LogFile::new(
+        "sync_counter".to_string(),
+        Box::new(syncs.clone()),
+    )));
+    let guard = JloggerGuard {
+        logger: Box::leak(Box::new(logger)),
     };
-    ($fmt:expr,$($val:expr),*) => {{
-        log::trace!(
-            "{}-{} : {}",
-            file!(),
-            line!(),
-            format!($fmt, $($val),*)
-        );
-    }};
+
+    for i in 0..5 {
+        test_log(guard.logger, log::Level::Info, &format!("batched {}", i));
+    }
+    assert_eq!(console_file.read(), "");
+
+    guard.flush_all();
+    assert_eq!(console_file.read().lines().count(), 5);
+    assert_eq!(syncs.count(), 1);
 }
 
 #[test]
-fn test_debug_macro() {
-    use log::{debug, info};
+fn test_local_with_uptime() {
+    fn boot_time() -> Option<i64> {
+        Some(chrono::Local::now().timestamp() - 9080)
+    }
 
-    JloggerBuilder::new()
-        .max_level(LevelFilter::Debug)
-        .log_console(true)
-        .log_runtime(true)
-        .log_time(LogTimeFormat::TimeLocal)
-        .log_file(Some("/tmp/abc"), false)
-        .build();
+    let log_file = TestFile::new("local_with_uptime.log");
 
-    jdebug!("test: {}", String::from("hello"));
-    jdebug!("this is debug");
+    let mut builder = log_file
+        .builder()
+        .log_time(LogTimeFormat::LocalWithUptime)
+        .timestamp_base(TimeStampBase::Epoch);
+    builder.boot_time = boot_time;
+    let logger = builder.into_logger();
+    test_log(&logger, log::Level::Info, "both");
 
-    std::thread::Builder::new()
-        .name("thread1".to_string())
-        .spawn(|| {
-            log::debug!(
-                "this is debug in the thread {}.",
-                std::thread::current().name().unwrap()
-            );
-            jinfo!("this is info in the thread.");
-        })
-        .unwrap()
-        .join()
+    let log = log_file.read();
+    let (local, rest) = log.split_at(19);
+    assert!(chrono::NaiveDateTime::parse_from_str(local, "%Y-%m-%d %H:%M:%S").is_ok());
+    let (uptime, rest) = rest
+        .strip_prefix(" [")
+        .and_then(|r| r.split_once("] "))
         .unwrap();
+    let uptime: f64 = uptime.parse().unwrap();
+    assert!((9080.0..9082.0).contains(&uptime));
+    assert_eq!(rest, "INFO  : both\n");
+}
 
-    jerror!("this is error");
-    jinfo!("this is info");
-    std::thread::spawn(|| {
-        log::debug!(
-            "this is debug in the thread {}.",
-            std::thread::current()
-                .name()
-                .unwrap_or("No thread name set"),
-        );
-        jinfo!("this is info in the thread.");
-    })
-    .join()
-    .unwrap();
-    info!("this is info");
-    jdebug!();
-    debug!("default");
+#[test]
+fn test_max_hourly_age() {
+    let log_file = TestFile::new("max_hourly_age.log");
+    let hourly: Vec<TestFile> = ["10", "11", "12", "13"]
+        .iter()
+        .map(|hour| TestFile::new(&format!("max_hourly_age.log-20240115-{}", hour)))
+        .collect();
+    let old = std::time::SystemTime::now() - Duration::from_secs(3 * 3600);
+    for f in &hourly[..2] {
+        File::create(f.path()).unwrap().set_modified(old).unwrap();
+    }
+    fs::write(hourly[2].path(), "recent\n").unwrap();
+
+    let mut builder = log_file
+        .builder()
+        .clock(FrozenClock::new(2024, 1, 15, 13, 0, 0))
+        .rotate_hourly(true)
+        .max_hourly_age(Duration::from_secs(3600));
+    builder.prepare().unwrap();
+
+    let exists: Vec<bool> = hourly
+        .iter()
+        .map(|f| std::path::Path::new(f.path()).exists())
+        .collect();
+    assert_eq!(exists, [false, false, true, true]);
+}
+
+#[test]
+fn test_max_hourly_files() {
+    let dir = TestDir::new("max_hourly_files");
+    let log_file = dir.path().join("test.log");
+    let log_file = log_file.to_str().unwrap();
+    for hour in ["20240115-10", "20240115-11", "20240115-12"] {
+        fs::write(format!("{}-{}", log_file, hour), "old\n").unwrap();
+    }
+    fs::write(dir.path().join("other.log-20240115-09"), "other\n").unwrap();
+    let clock = FrozenClock::new(2024, 1, 15, 13, 59, 59);
+
+    let mut builder = JloggerBuilder::new()
+        .log_console(false)
+        .log_file(Some(log_file), false)
+        .clock(clock.clone())
+        .rotate_hourly(true)
+        .max_hourly_files(2);
+    builder.prepare().unwrap();
+    let logger = builder.into_logger();
+    test_log(&logger, log::Level::Info, "first hour");
+    clock.advance(chrono::Duration::seconds(1));
+    test_log(&logger, log::Level::Info, "second hour");
+
+    let mut names: Vec<String> = fs::read_dir(dir.path())
+        .unwrap()
+        .map(|e| e.unwrap().file_name().into_string().unwrap())
+        .collect();
+    names.sort();
+    assert_eq!(
+        names,
+        [
+            "other.log-20240115-09",
+            "test.log-20240115-13",
+            "test.log-20240115-14"
+        ]
+    );
 }
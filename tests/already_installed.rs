@@ -0,0 +1,34 @@
+use jlogger::{JloggerBuilder, JloggerError};
+use std::fs;
+
+#[test]
+fn test_already_installed() {
+    let pid_file = std::env::temp_dir().join("jlogger_test_already_installed.pid");
+    let pid_file = pid_file.to_str().unwrap();
+    let _ = fs::remove_file(pid_file);
+    let log_file = std::env::temp_dir().join("jlogger_test_already_installed.log");
+    let log_file = log_file.to_str().unwrap();
+    fs::write(log_file, "INFO  : from the installed logger\n").unwrap();
+
+    jlogger::init_default();
+
+    assert!(matches!(
+        JloggerBuilder::new().pid_file(pid_file).try_build(),
+        Err(JloggerError::AlreadyInstalled)
+    ));
+    assert!(!std::path::Path::new(pid_file).exists());
+
+    // The failed call must not truncate or lock the log file of the installed logger.
+    for _ in 0..2 {
+        assert!(matches!(
+            JloggerBuilder::new()
+                .log_file(Some(log_file), false)
+                .exclusive_lock(true)
+                .try_build(),
+            Err(JloggerError::AlreadyInstalled)
+        ));
+    }
+    let content = fs::read_to_string(log_file).unwrap();
+    let _ = fs::remove_file(log_file);
+    assert_eq!(content, "INFO  : from the installed logger\n");
+}
@@ -0,0 +1,24 @@
+use jlogger::{JloggerBuilder, LogTimeFormat};
+use std::fs;
+
+#[test]
+fn test_backtrace_on_error() {
+    let log_file = std::env::temp_dir().join("jlogger_test_backtrace_on_error.log");
+    let log_file = log_file.to_str().unwrap();
+
+    std::env::set_var("RUST_BACKTRACE", "1");
+    let guard = JloggerBuilder::new()
+        .log_console(false)
+        .log_file(Some(log_file), false)
+        .log_time(LogTimeFormat::TimeNone)
+        .backtrace_on_error(true)
+        .build();
+    log::warn!("no backtrace");
+    log::error!("with backtrace");
+    drop(guard);
+
+    let log = fs::read_to_string(log_file).unwrap();
+    let _ = fs::remove_file(log_file);
+    assert!(log.starts_with("WARN  : no backtrace\nERROR : with backtrace\nstack backtrace:\n"));
+    assert!(log.contains("test_backtrace_on_error"));
+}
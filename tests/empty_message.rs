@@ -0,0 +1,23 @@
+use jlogger::{jdebug, JloggerBuilder};
+use log::LevelFilter;
+
+#[test]
+fn test_empty_message() {
+    let log_file = std::env::temp_dir().join("jlogger_test_empty_message.log");
+    let log_file = log_file.to_str().unwrap();
+
+    let guard = JloggerBuilder::new()
+        .max_level(LevelFilter::Debug)
+        .log_console(false)
+        .log_file(Some(log_file), false)
+        .log_time(jlogger::LogTimeFormat::TimeNone)
+        .empty_message("here")
+        .build();
+    jdebug!();
+    drop(guard);
+
+    let content = std::fs::read_to_string(log_file).unwrap();
+    let _ = std::fs::remove_file(log_file);
+    assert!(content.starts_with("DEBUG : tests/empty_message.rs-"));
+    assert!(content.ends_with(" : here\n"));
+}
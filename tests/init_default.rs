@@ -0,0 +1,11 @@
+use log::{Level, Metadata};
+
+#[test]
+fn test_init_default() {
+    jlogger::init_default();
+    jlogger::init_default();
+
+    assert!(log::logger().enabled(&Metadata::builder().level(Level::Info).build()));
+    assert!(!log::logger().enabled(&Metadata::builder().level(Level::Debug).build()));
+    jlogger::jinfo!("logged after init_default");
+}
@@ -0,0 +1,31 @@
+use jlogger::JloggerError;
+use std::fs;
+
+#[test]
+fn test_init_from_env() {
+    let log_file = std::env::temp_dir().join("jlogger_test_init_from_env.log");
+    let log_file = log_file.to_str().unwrap();
+    let _ = fs::remove_file(log_file);
+
+    std::env::set_var("JLOGGER_FORMAT", "json");
+    match jlogger::init_from_env() {
+        Err(JloggerError::InvalidEnv { name, value }) => {
+            assert_eq!(name, "JLOGGER_FORMAT");
+            assert_eq!(value, "json");
+        }
+        _ => panic!("invalid JLOGGER_FORMAT accepted"),
+    }
+
+    std::env::set_var("JLOGGER_LEVEL", "debug");
+    std::env::set_var("JLOGGER_FILE", log_file);
+    std::env::set_var("JLOGGER_FORMAT", "compact");
+    std::env::set_var("JLOGGER_TIME", "none");
+    let guard = jlogger::init_from_env().unwrap();
+    log::debug!("configured from env");
+    log::trace!("filtered out");
+    drop(guard);
+
+    let content = fs::read_to_string(log_file).unwrap();
+    let _ = fs::remove_file(log_file);
+    assert_eq!(content, "D : configured from env\n");
+}
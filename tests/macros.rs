@@ -0,0 +1,229 @@
+use jlogger::{
+    jdebug_assert, jdebug_compact, jdebug_once, jdebug_pretty, jerror_chain, jerror_once, jhex,
+    jinfo_once, jstatus, jtap, jtrace_once, jwarn_once,
+};
+use log::{LevelFilter, Log, Metadata, Record};
+use std::sync::{Mutex, Once};
+
+/// Logger capturing every record, so that the output of the macros can be checked.
+struct CaptureLogger;
+
+static CAPTURED: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+impl Log for CaptureLogger {
+    fn enabled(&self, _metadata: &Metadata) -> bool {
+        true
+    }
+
+    fn log(&self, record: &Record) {
+        CAPTURED
+            .lock()
+            .unwrap()
+            .push(format!("{} {}", record.level(), record.args()));
+    }
+
+    fn flush(&self) {}
+}
+
+/// Records logged so far containing `pattern`.
+fn captured(pattern: &str) -> Vec<String> {
+    static INIT: Once = Once::new();
+    INIT.call_once(|| {
+        log::set_logger(&CaptureLogger).unwrap();
+        log::set_max_level(LevelFilter::Trace);
+    });
+
+    CAPTURED
+        .lock()
+        .unwrap()
+        .iter()
+        .filter(|r| r.contains(pattern))
+        .cloned()
+        .collect()
+}
+
+#[test]
+fn test_once_macros() {
+    captured("");
+
+    for i in 0..5 {
+        jerror_once!("error once");
+        jwarn_once!("warn once {}", i);
+        jinfo_once!("info once");
+        jdebug_once!("debug once");
+        jtrace_once!("trace once");
+    }
+
+    assert_eq!(captured("error once").len(), 1);
+    let warn = captured("warn once");
+    assert_eq!(warn.len(), 1);
+    assert!(warn[0].ends_with(" : warn once 0"));
+    assert_eq!(captured("info once").len(), 1);
+    assert_eq!(captured("debug once").len(), 1);
+    assert_eq!(captured("trace once").len(), 1);
+}
+
+#[test]
+fn test_jhex() {
+    captured("");
+
+    let buf: Vec<u8> = (0x41..0x55).collect();
+    jhex!(&buf);
+    jhex!(&buf[..4], 4, false);
+
+    let dumps = captured(" bytes\n");
+    assert_eq!(dumps.len(), 2);
+    assert!(dumps[0].starts_with("DEBUG tests/macros.rs-"));
+    assert!(dumps[0].ends_with(
+        " : 20 bytes\n\
+         00000000  41 42 43 44 45 46 47 48 49 4a 4b 4c 4d 4e 4f 50  |ABCDEFGHIJKLMNOP|\n\
+         00000010  51 52 53 54                                      |QRST|"
+    ));
+    assert!(dumps[1].ends_with(" : 4 bytes\n00000000  41 42 43 44"));
+}
+
+#[test]
+fn test_jtap() {
+    captured("");
+
+    let mut calls = 0;
+    let mut next = || {
+        calls += 1;
+        calls * 10
+    };
+    let value = jtap!(next()) + jtap!(String::from("tapped"), "label").len();
+
+    assert_eq!(value, 16);
+    assert_eq!(calls, 1);
+    let tap = captured(" : next() = ");
+    assert_eq!(tap.len(), 1);
+    assert!(tap[0].starts_with("TRACE tests/macros.rs-"));
+    assert!(tap[0].ends_with(" : next() = 10"));
+    let tap = captured(" : label = ");
+    assert_eq!(tap.len(), 1);
+    assert!(tap[0].ends_with(" : label = \"tapped\""));
+}
+
+#[test]
+fn test_jerror_chain() {
+    #[derive(Debug)]
+    struct ConnectError(std::io::Error);
+
+    impl std::fmt::Display for ConnectError {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            write!(f, "failed to connect to db")
+        }
+    }
+
+    impl std::error::Error for ConnectError {
+        fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+            Some(&self.0)
+        }
+    }
+
+    captured("");
+
+    let err = ConnectError(std::io::Error::new(
+        std::io::ErrorKind::ConnectionRefused,
+        "connection refused",
+    ));
+    jerror_chain!(err);
+    let boxed: Box<dyn std::error::Error> = Box::new(err);
+    jerror_chain!(boxed.as_ref());
+
+    let chains = captured("failed to connect to db");
+    assert_eq!(chains.len(), 2);
+    for chain in chains {
+        assert!(chain.starts_with("ERROR tests/macros.rs-"));
+        assert!(chain.ends_with(" : failed to connect to db\n  caused by: connection refused"));
+    }
+}
+
+#[test]
+fn test_jstatus() {
+    captured("");
+
+    for code in [503, 404, 200] {
+        jstatus!(code, "request finished with {}", code);
+    }
+
+    let records = captured("request finished with");
+    assert_eq!(records.len(), 3);
+    assert!(records[0].starts_with("ERROR "));
+    assert!(records[0].ends_with(" : request finished with 503"));
+    assert!(records[1].starts_with("WARN "));
+    assert!(records[2].starts_with("INFO "));
+}
+
+#[test]
+fn test_jdebug_pretty() {
+    captured("");
+
+    let config = std::collections::BTreeMap::from([("port", 8080), ("workers", 4)]);
+    jdebug_compact!(&config);
+    jdebug_pretty!(&config);
+
+    let records = captured(" : &config = ");
+    assert_eq!(records.len(), 2);
+    assert!(records[0].ends_with(" : &config = {\"port\": 8080, \"workers\": 4}"));
+    assert!(!records[0].contains('\n'));
+    assert!(records[1].ends_with(" : &config = {\n    \"port\": 8080,\n    \"workers\": 4,\n}"));
+}
+
+#[test]
+fn test_jdebug_assert() {
+    captured("");
+
+    let mut evaluated = 0;
+    let mut check = |ok: bool| {
+        evaluated += 1;
+        ok
+    };
+    jdebug_assert!(check(true), "never logged");
+    jdebug_assert!(check(false), "invariant broken: {}", 42);
+    jdebug_assert!(check(1 + 1 == 3));
+
+    assert!(captured("never logged").is_empty());
+    if cfg!(debug_assertions) {
+        assert_eq!(evaluated, 3);
+        let records = captured("invariant broken");
+        assert_eq!(records.len(), 1);
+        assert!(records[0].starts_with("ERROR tests/macros.rs-"));
+        assert!(records[0].ends_with(" : invariant broken: 42"));
+        let records = captured(" : assertion failed: check(1 + 1 == 3)");
+        assert_eq!(records.len(), 1);
+    } else {
+        assert_eq!(evaluated, 0);
+        assert!(captured("invariant broken").is_empty());
+    }
+}
+
+#[test]
+#[cfg_attr(debug_assertions, should_panic(expected = "queue overflow: 120"))]
+fn test_jdebug_assert_panic() {
+    captured("");
+
+    jdebug_assert!(panic, 120 < 100, "queue overflow: {}", 120);
+}
+
+#[test]
+fn test_jdebug_assert_panic_formats_once() {
+    captured("");
+
+    let mut evaluated = 0;
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        jdebug_assert!(panic, 1 > 2, "formatted {} time", {
+            evaluated += 1;
+            evaluated
+        });
+    }));
+
+    if cfg!(debug_assertions) {
+        assert!(result.is_err());
+        assert_eq!(evaluated, 1);
+        assert_eq!(captured("formatted 1 time").len(), 1);
+    } else {
+        assert!(result.is_ok());
+        assert_eq!(evaluated, 0);
+    }
+}
@@ -0,0 +1,16 @@
+use jlogger::JloggerBuilder;
+
+#[test]
+fn test_pid_file() {
+    let pid_file = std::env::temp_dir().join("jlogger_test_pid_file.pid");
+    let pid_file = pid_file.to_str().unwrap();
+
+    let guard = JloggerBuilder::new().pid_file(pid_file).build();
+    assert_eq!(
+        std::fs::read_to_string(pid_file).unwrap(),
+        format!("{}\n", std::process::id())
+    );
+
+    drop(guard);
+    assert!(!std::path::Path::new(pid_file).exists());
+}
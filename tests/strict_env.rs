@@ -0,0 +1,17 @@
+use jlogger::{JloggerBuilder, JloggerError};
+
+#[test]
+fn test_strict_env() {
+    std::env::set_var("JLOGGER_LEVEL", "bogus");
+
+    match JloggerBuilder::new().strict_env(true).try_build() {
+        Err(JloggerError::InvalidEnv { name, value }) => {
+            assert_eq!(name, "JLOGGER_LEVEL");
+            assert_eq!(value, "bogus");
+        }
+        _ => panic!("invalid JLOGGER_LEVEL accepted"),
+    }
+
+    std::env::set_var("JLOGGER_LEVEL", "Debug");
+    assert!(JloggerBuilder::new().strict_env(true).try_build().is_ok());
+}